@@ -0,0 +1,239 @@
+// Discord listens on a named pipe on Windows instead.
+#![cfg(unix)]
+
+use serde_json::{Value, json};
+use std::io::{Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+const HANDSHAKE: u32 = 0;
+const FRAME: u32 = 1;
+const CLOSE: u32 = 2;
+const PING: u32 = 3;
+const PONG: u32 = 4;
+
+/// How long `wait_for_activities` waits before giving up.
+const WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+struct State {
+    client_ids: Vec<String>,
+    /// Received `SET_ACTIVITY` payloads, `None` for a clear.
+    activities: Vec<Option<Value>>,
+    /// The open connections, to close them from the test.
+    connections: Vec<UnixStream>,
+    refusing: bool,
+}
+
+/// Stands in for the Discord client's IPC socket, for tests. It listens on
+/// `discord-ipc-0` in a temporary directory; set `XDG_RUNTIME_DIR` to
+/// `runtime_dir` and the Discord client connects to it. Stops and removes the
+/// directory when dropped.
+pub struct MockDiscord {
+    dir: PathBuf,
+    state: Arc<(Mutex<State>, Condvar)>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl MockDiscord {
+    pub fn start() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "discord-mock-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::SeqCst)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let listener = UnixListener::bind(dir.join("discord-ipc-0")).unwrap();
+        let state = Arc::new((Mutex::new(State::default()), Condvar::new()));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let (thread_state, thread_stopped) = (state.clone(), stopped.clone());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if thread_stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                let state = thread_state.clone();
+                std::thread::spawn(move || serve(stream, &state));
+            }
+        });
+        Self {
+            dir,
+            state,
+            stopped,
+        }
+    }
+
+    /// What to set `XDG_RUNTIME_DIR` to.
+    pub fn runtime_dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.0.lock().unwrap()
+    }
+
+    /// The client IDs of every handshake so far, one per connection.
+    pub fn client_ids(&self) -> Vec<String> {
+        self.state().client_ids.clone()
+    }
+
+    /// Every activity set so far, oldest first. A cleared activity is `None`.
+    pub fn activities(&self) -> Vec<Option<Value>> {
+        self.state().activities.clone()
+    }
+
+    /// The activity Discord would be showing, if any.
+    pub fn current(&self) -> Option<Value> {
+        self.state().activities.last().cloned().flatten()
+    }
+
+    /// Waits until `done` holds for the activities set so far, for clients
+    /// running on another thread or process. Panics after 10 seconds.
+    pub fn wait_for(&self, done: impl Fn(&[Option<Value>]) -> bool) -> Vec<Option<Value>> {
+        let deadline = Instant::now() + WAIT_TIMEOUT;
+        let (lock, changed) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        while !done(&state.activities) {
+            let now = Instant::now();
+            if now >= deadline {
+                panic!("gave up waiting on activities: {:?}", state.activities);
+            }
+            state = changed.wait_timeout(state, deadline - now).unwrap().0;
+        }
+        state.activities.clone()
+    }
+
+    /// Waits until at least `count` activities were set.
+    pub fn wait_for_activities(&self, count: usize) -> Vec<Option<Value>> {
+        self.wait_for(|activities| activities.len() >= count)
+    }
+
+    /// Drops every open connection, as when Discord quits or restarts. The
+    /// client's next write fails.
+    pub fn disconnect(&self) {
+        for connection in self.state().connections.drain(..) {
+            let _ = connection.shutdown(Shutdown::Both);
+        }
+    }
+
+    /// While set, connections are closed without a handshake, as when
+    /// Discord is starting up.
+    pub fn refuse_connections(&self, refusing: bool) {
+        self.state().refusing = refusing;
+    }
+}
+
+impl Drop for MockDiscord {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.disconnect();
+        // Wakes the listener so it sees the flag.
+        let _ = UnixStream::connect(self.dir.join("discord-ipc-0"));
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn read_frame(stream: &mut UnixStream) -> std::io::Result<(u32, Value)> {
+    let mut header = [0; 8];
+    stream.read_exact(&mut header)?;
+    let opcode = u32::from_le_bytes(header[..4].try_into().unwrap());
+    let len = u32::from_le_bytes(header[4..].try_into().unwrap());
+    let mut data = vec![0; len as usize];
+    stream.read_exact(&mut data)?;
+    let data = serde_json::from_slice(&data).map_err(std::io::Error::other)?;
+    Ok((opcode, data))
+}
+
+fn write_frame(stream: &mut UnixStream, opcode: u32, data: &Value) -> std::io::Result<()> {
+    let data = data.to_string();
+    let mut frame = Vec::with_capacity(8 + data.len());
+    frame.extend(opcode.to_le_bytes());
+    frame.extend((data.len() as u32).to_le_bytes());
+    frame.extend(data.as_bytes());
+    stream.write_all(&frame)
+}
+
+fn serve(mut stream: UnixStream, state: &(Mutex<State>, Condvar)) {
+    let (lock, changed) = state;
+    {
+        let mut state = lock.lock().unwrap();
+        if state.refusing {
+            return;
+        }
+        if let Ok(connection) = stream.try_clone() {
+            state.connections.push(connection);
+        }
+    }
+    if let Err(err) = handshake(&mut stream, lock) {
+        eprintln!("Mock Discord handshake error: {}", err);
+        return;
+    }
+    // Errors from here on are the client going away.
+    while let Ok((opcode, data)) = read_frame(&mut stream) {
+        match opcode {
+            FRAME if data["cmd"] == "SET_ACTIVITY" => {
+                let activity = Some(data["args"]["activity"].clone()).filter(|a| !a.is_null());
+                let reply = json!({
+                    "cmd": "SET_ACTIVITY",
+                    "data": activity,
+                    "evt": null,
+                    "nonce": data["nonce"],
+                });
+                lock.lock().unwrap().activities.push(activity);
+                changed.notify_all();
+                // The client does not have to read the reply.
+                let _ = write_frame(&mut stream, FRAME, &reply);
+            }
+            FRAME => {
+                let reply = json!({
+                    "cmd": data["cmd"],
+                    "data": {"code": 4000, "message": "Unknown command"},
+                    "evt": "ERROR",
+                    "nonce": data["nonce"],
+                });
+                let _ = write_frame(&mut stream, FRAME, &reply);
+            }
+            PING => {
+                let _ = write_frame(&mut stream, PONG, &data);
+            }
+            _ => break,
+        }
+    }
+}
+
+fn handshake(stream: &mut UnixStream, state: &Mutex<State>) -> std::io::Result<()> {
+    let (opcode, data) = read_frame(stream)?;
+    let client_id = data["client_id"].as_str().unwrap_or_default();
+    if opcode != HANDSHAKE || data["v"] != 1 || client_id.is_empty() {
+        let close = json!({"code": 4000, "message": "Invalid handshake"});
+        write_frame(stream, CLOSE, &close)?;
+        return Err(std::io::Error::other(format!(
+            "invalid handshake: {}",
+            data
+        )));
+    }
+    state.lock().unwrap().client_ids.push(client_id.to_string());
+    let ready = json!({
+        "cmd": "DISPATCH",
+        "evt": "READY",
+        "data": {
+            "v": 1,
+            "config": {
+                "cdn_host": "cdn.discordapp.com",
+                "api_endpoint": "//discord.com/api",
+                "environment": "production",
+            },
+            "user": {"id": "0", "username": "someone", "discriminator": "0", "avatar": null},
+        },
+        "nonce": null,
+    });
+    write_frame(stream, FRAME, &ready)
+}
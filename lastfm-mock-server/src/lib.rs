@@ -0,0 +1,435 @@
+use serde_json::{Value, json};
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// A track for scripted `user.getRecentTracks` responses, also found by
+/// `track.getInfo`.
+#[derive(Clone, Debug, Default)]
+pub struct MockTrack {
+    pub name: String,
+    pub artist: String,
+    pub album: String,
+    pub now_playing: bool,
+    /// In milliseconds, as Last.fm gives it. 0 if unknown.
+    pub duration: u64,
+    /// The user's play count, given by `track.getInfo` when asked with a
+    /// username.
+    pub user_playcount: Option<u64>,
+}
+
+impl MockTrack {
+    pub fn playing(name: &str, artist: &str, album: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            artist: artist.to_string(),
+            album: album.to_string(),
+            now_playing: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn played(name: &str, artist: &str, album: &str) -> Self {
+        Self {
+            now_playing: false,
+            ..Self::playing(name, artist, album)
+        }
+    }
+
+    fn url(&self) -> String {
+        format!(
+            "https://www.last.fm/music/{}/_/{}",
+            self.artist.replace(' ', "+"),
+            self.name.replace(' ', "+")
+        )
+    }
+
+    fn recent_json(&self) -> Value {
+        let mut track = json!({
+            "artist": {"mbid": "", "#text": self.artist},
+            "streamable": "0",
+            "image": [],
+            "mbid": "",
+            "album": {"mbid": "", "#text": self.album},
+            "name": self.name,
+            "url": self.url(),
+        });
+        if self.now_playing {
+            track["@attr"] = json!({"nowplaying": "true"});
+        } else {
+            track["date"] = json!({"uts": "1732873371", "#text": "29 Nov 2024, 09:42"});
+        }
+        track
+    }
+
+    fn info_json(&self, with_user: bool) -> Value {
+        let mut track = json!({
+            "name": self.name,
+            "url": self.url(),
+            "duration": self.duration.to_string(),
+            "artist": {"name": self.artist, "mbid": "", "url": ""},
+            "album": {"artist": self.artist, "title": self.album, "mbid": "", "url": "", "image": []},
+        });
+        if with_user && let Some(playcount) = self.user_playcount {
+            track["userplaycount"] = json!(playcount.to_string());
+        }
+        json!({"track": track})
+    }
+}
+
+/// A request the server answered.
+#[derive(Clone, Debug)]
+pub struct ReceivedRequest {
+    /// `GET` or `POST`.
+    pub http_method: String,
+    /// From the query, and the body of a POST.
+    pub params: HashMap<String, String>,
+}
+
+impl ReceivedRequest {
+    /// The Last.fm method, such as `auth.getToken`.
+    pub fn method(&self) -> &str {
+        self.params.get("method").map_or("", String::as_str)
+    }
+}
+
+enum TokenState {
+    /// Waiting for the user to approve it.
+    Pending,
+    Approved,
+    /// Exchanged for a session already.
+    Used,
+}
+
+struct State {
+    api_key: String,
+    secret: String,
+    user: String,
+    password: Option<String>,
+    tokens: Vec<(String, TokenState)>,
+    sessions: Vec<String>,
+    recent_tracks: VecDeque<Vec<MockTrack>>,
+    requests: Vec<ReceivedRequest>,
+    next_id: u32,
+}
+
+/// Serves the Last.fm 2.0 API on 127.0.0.1, for tests. Requests must carry
+/// the configured API key, and signatures are checked against the secret.
+/// Stops when dropped.
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl MockServer {
+    pub fn start(api_key: &str, secret: &str) -> Self {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(State {
+            api_key: api_key.to_string(),
+            secret: secret.to_string(),
+            user: String::from("someone"),
+            password: None,
+            tokens: Vec::new(),
+            sessions: Vec::new(),
+            recent_tracks: VecDeque::new(),
+            requests: Vec::new(),
+            next_id: 1,
+        }));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let (thread_state, thread_stopped) = (state.clone(), stopped.clone());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if thread_stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Ok(stream) = stream
+                    && let Err(err) = handle(stream, &thread_state)
+                {
+                    eprintln!("Mock Last.fm error: {}", err);
+                }
+            }
+        });
+        Self {
+            addr,
+            state,
+            stopped,
+        }
+    }
+
+    /// What to give the agents, or eclect's `--endpoint`.
+    pub fn endpoint(&self) -> String {
+        format!("http://{}/2.0/", self.addr)
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// Names the user that sessions belong to. `someone` by default.
+    pub fn set_user(&self, name: &str) {
+        self.state().user = name.to_string();
+    }
+
+    /// Lets `auth.getMobileSession` log in as the user with `password`.
+    pub fn set_password(&self, password: &str) {
+        self.state().password = Some(password.to_string());
+    }
+
+    /// Tokens given out by `auth.getToken`, oldest first.
+    pub fn tokens(&self) -> Vec<String> {
+        self.state()
+            .tokens
+            .iter()
+            .map(|(token, _)| token.clone())
+            .collect()
+    }
+
+    /// Does what the user would on Last.fm's authorization page.
+    pub fn approve(&self, token: &str) {
+        let mut state = self.state();
+        match state.tokens.iter_mut().find(|(known, _)| known == token) {
+            Some((_, state @ TokenState::Pending)) => *state = TokenState::Approved,
+            Some(_) => panic!("token {} was already approved", token),
+            None => panic!("token {} was not given out", token),
+        }
+    }
+
+    /// Makes `key` a valid session, without going through authorization.
+    pub fn add_session(&self, key: &str) {
+        self.state().sessions.push(key.to_string());
+    }
+
+    /// Ends a session, as when the user revokes access.
+    pub fn revoke_session(&self, key: &str) {
+        self.state().sessions.retain(|known| known != key);
+    }
+
+    /// Queues the tracks for the next `user.getRecentTracks`, newest first.
+    /// The last tracks queued keep being returned once the rest are used.
+    pub fn push_recent_tracks(&self, tracks: Vec<MockTrack>) {
+        self.state().recent_tracks.push_back(tracks);
+    }
+
+    /// Every request so far, including rejected ones.
+    pub fn requests(&self) -> Vec<ReceivedRequest> {
+        self.state().requests.clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // Wakes the listener so it sees the flag.
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+fn handle(stream: TcpStream, state: &Mutex<State>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut content_length = 0;
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && line != "\r\n" {
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse().unwrap_or(0);
+        }
+        line.clear();
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let mut parts = request_line.split_whitespace();
+    let (Some(http_method), Some(target)) = (parts.next(), parts.next()) else {
+        return Ok(());
+    };
+    let url = url::Url::parse("http://localhost/")
+        .and_then(|base| base.join(target))
+        .map_err(std::io::Error::other)?;
+    let mut params = url.query_pairs().into_owned().collect::<HashMap<_, _>>();
+    params.extend(url::form_urlencoded::parse(&body).into_owned());
+    let (status, response) = state.lock().unwrap().respond(ReceivedRequest {
+        http_method: http_method.to_string(),
+        params,
+    });
+    let response = response.to_string();
+    write!(
+        reader.get_mut(),
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        response.len(),
+        response
+    )?;
+    reader.get_mut().flush()
+}
+
+/// A Last.fm error, with the HTTP status Last.fm sends it with.
+fn error(code: u32, message: &str) -> (&'static str, Value) {
+    let status = match code {
+        4 | 9 | 10 | 13 | 14 | 15 | 26 => "403 Forbidden",
+        11 | 16 => "503 Service Unavailable",
+        29 => "429 Too Many Requests",
+        _ => "400 Bad Request",
+    };
+    (status, json!({"message": message, "error": code}))
+}
+
+impl State {
+    fn respond(&mut self, request: ReceivedRequest) -> (&'static str, Value) {
+        self.requests.push(request.clone());
+        let params = &request.params;
+        let param = |name: &str| params.get(name).map(String::as_str);
+        if param("api_key") != Some(&self.api_key) {
+            return error(
+                10,
+                "Invalid API key - You must be granted a valid key by last.fm",
+            );
+        }
+        let method = param("method").unwrap_or_default();
+        match param("api_sig") {
+            Some(signature) if signature != self.signature(params) => {
+                return error(13, "Invalid method signature supplied");
+            }
+            None if method.starts_with("auth.") => {
+                return error(13, "Invalid method signature supplied");
+            }
+            _ => {}
+        }
+        if let Some(session_key) = param("sk")
+            && !self.sessions.iter().any(|known| known == session_key)
+        {
+            return error(9, "Invalid session key - Please re-authenticate");
+        }
+        match method {
+            "auth.getToken" => {
+                let token = format!("token{}", self.next_id());
+                self.tokens.push((token.clone(), TokenState::Pending));
+                ("200 OK", json!({"token": token}))
+            }
+            "auth.getSession" => {
+                let token = param("token").unwrap_or_default();
+                match self.tokens.iter_mut().find(|(known, _)| known == token) {
+                    None => error(4, "Invalid authentication token supplied"),
+                    Some((_, TokenState::Pending)) => error(
+                        14,
+                        "Unauthorized Token - This token has not been authorized",
+                    ),
+                    Some((_, TokenState::Used)) => error(15, "This token has expired"),
+                    Some((_, state @ TokenState::Approved)) => {
+                        *state = TokenState::Used;
+                        self.new_session()
+                    }
+                }
+            }
+            "auth.getMobileSession" => {
+                if request.http_method != "POST" {
+                    return error(3, "Invalid Method - This method must be posted");
+                }
+                if param("username") == Some(&self.user)
+                    && self
+                        .password
+                        .as_deref()
+                        .is_some_and(|password| param("password") == Some(password))
+                {
+                    self.new_session()
+                } else {
+                    error(
+                        4,
+                        "Authentication Failed - You do not have permissions to access the service",
+                    )
+                }
+            }
+            "user.getInfo" => ("200 OK", self.user_json()),
+            "user.getRecentTracks" => {
+                let tracks = if self.recent_tracks.len() > 1 {
+                    self.recent_tracks.pop_front().unwrap_or_default()
+                } else {
+                    self.recent_tracks.front().cloned().unwrap_or_default()
+                };
+                let total = tracks.len().to_string();
+                let tracks = tracks
+                    .iter()
+                    .map(MockTrack::recent_json)
+                    .collect::<Vec<_>>();
+                (
+                    "200 OK",
+                    json!({"recenttracks": {
+                        "track": tracks,
+                        "@attr": {"user": self.user, "totalPages": "1", "page": "1", "perPage": total, "total": total},
+                    }}),
+                )
+            }
+            "track.getInfo" => {
+                let found = self.recent_tracks.iter().flatten().find(|track| {
+                    param("track") == Some(&track.name) && param("artist") == Some(&track.artist)
+                });
+                match found {
+                    Some(track) => ("200 OK", track.info_json(param("username").is_some())),
+                    None => error(6, "Track not found"),
+                }
+            }
+            _ => error(
+                3,
+                "Invalid Method - No method with that name in this package",
+            ),
+        }
+    }
+
+    fn next_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn new_session(&mut self) -> (&'static str, Value) {
+        let key = format!("session{}", self.next_id());
+        self.sessions.push(key.clone());
+        (
+            "200 OK",
+            json!({"session": {"name": self.user, "key": key, "subscriber": 0}}),
+        )
+    }
+
+    fn user_json(&self) -> Value {
+        json!({"user": {
+            "name": self.user,
+            "age": "0",
+            "gender": "n",
+            "subscriber": "0",
+            "realname": "",
+            "bootstrap": "0",
+            "playcount": "1000",
+            "artist_count": "100",
+            "track_count": "500",
+            "album_count": "200",
+            "playlists": "0",
+            "image": [],
+            "registered": {"unixtime": "1350000000", "#text": 1350000000},
+            "country": "",
+            "url": format!("https://www.last.fm/user/{}", self.user),
+            "type": "user",
+        }})
+    }
+
+    /// Signs like Last.fm: every parameter but `format`, `callback` and the
+    /// signature itself, sorted, then the secret.
+    fn signature(&self, params: &HashMap<String, String>) -> String {
+        let mut signed = params
+            .iter()
+            .filter(|(key, _)| !matches!(key.as_str(), "format" | "callback" | "api_sig"))
+            .collect::<Vec<_>>();
+        signed.sort();
+        let mut prehash = signed
+            .into_iter()
+            .map(|(key, value)| format!("{}{}", key, value))
+            .collect::<String>();
+        prehash.push_str(&self.secret);
+        format!("{:x}", md5::compute(prehash))
+    }
+}
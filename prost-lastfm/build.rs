@@ -24,11 +24,24 @@ fn main() -> Result<(), String> {
         .field_attribute("attr", "#[serde(rename = \"@attr\")]")
         .type_attribute(".", "#[serde_macros::default_deserialize_with(u32 => crate::shim::parse_from_string)]")
         .type_attribute(".", "#[serde_macros::default_deserialize_with(::core::option::Option<u32> => crate::shim::parse_option_from_string)]")
+        .type_attribute(".", "#[serde_macros::default_deserialize_with(u64 => crate::shim::parse_from_string)]")
+        .type_attribute(".", "#[serde_macros::default_deserialize_with(::core::option::Option<u64> => crate::shim::parse_option_from_string)]")
         .type_attribute(".", "#[serde_macros::default_deserialize_with(bool => crate::shim::parse_bool)]")
         .type_attribute(".", "#[serde_macros::default_deserialize_with(::core::option::Option<bool> => crate::shim::parse_option_bool)]")
         .type_attribute(".", "#[derive(::serde::Deserialize, ::serde::Serialize)]")
         .type_attribute("lastfm.ListAttributes", "#[serde(rename_all = \"camelCase\")]")
         .type_attribute("lastfm.error.Error.Error", "#[serde(untagged)]")
+        .type_attribute("lastfm.tracks.CorrectedText", "#[serde(default)]")
+        .type_attribute("lastfm.tracks.IgnoredMessage", "#[serde(default)]")
+        .type_attribute("lastfm.tracks.TrackInfo", "#[serde(default)]")
+        .type_attribute("lastfm.tracks.TrackInfo.Artist", "#[serde(default)]")
+        .type_attribute("lastfm.tracks.TrackInfo.Album", "#[serde(default)]")
+        .type_attribute("lastfm.tracks.Scrobble", "#[serde(rename_all = \"camelCase\")]")
+        .type_attribute("lastfm.tracks.ScrobbleResult", "#[serde(rename_all = \"camelCase\")]")
+        .type_attribute("lastfm.tracks.UpdateNowPlayingRequest", "#[serde(rename_all = \"camelCase\")]")
+        .type_attribute("lastfm.tracks.NowPlayingResult", "#[serde(rename_all = \"camelCase\")]")
+        .field_attribute("lastfm.tracks.IgnoredMessage.code", "#[serde(deserialize_with = \"crate::shim::parse_from_string\")]")
+        .field_attribute("lastfm.tracks.Scrobbles.scrobble", "#[serde(deserialize_with = \"crate::shim::one_or_many\")]")
         .extension_registry(registry)
         .service_generator(Box::new(service_generator::ServiceGeneratorMacroWrapper(
             service_generator::LastFMRPCGenerator { async_setting },
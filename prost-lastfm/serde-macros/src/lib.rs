@@ -75,6 +75,10 @@ fn apply_deserialize_with(
                 if meta.path.is_ident("deserialize_with") {
                     has_deserialize_with = true;
                 }
+                // Consume values of unrelated keys such as `rename = "#text"`.
+                if meta.input.peek(syn::Token![=]) {
+                    meta.value()?.parse::<syn::Expr>()?;
+                }
                 Ok(())
             })?;
             if has_deserialize_with {
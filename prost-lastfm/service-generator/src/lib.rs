@@ -20,17 +20,25 @@ pub struct LastFMRPCGenerator {
 impl service_macro::ServiceGeneratorMacro for LastFMRPCGenerator {
     fn generate(&mut self, service: Service) -> proc_macro2::TokenStream {
         let mut tokens = proc_macro2::TokenStream::new();
+        if let AsyncSetting::None = &self.async_setting {
+            return tokens;
+        }
+        tokens.extend(service::mock_service_struct(&service));
+        tokens.extend(service::response_types(&service));
         if let AsyncSetting::SyncOnly | AsyncSetting::SyncAndAsync = &self.async_setting {
             tokens.extend(service::service_trait(&service, false, false));
-            tokens.extend(service::reqwest_service_impl(&service, false, false));
+            tokens.extend(service::transport_service_impl(&service, false, false));
+            tokens.extend(service::mock_service_impl(&service, false, false));
         }
         if let AsyncSetting::AsyncOnly | AsyncSetting::SyncAndAsync = &self.async_setting {
             tokens.extend(service::service_trait(&service, true, true));
-            tokens.extend(service::reqwest_service_impl(&service, true, true));
+            tokens.extend(service::transport_service_impl(&service, true, true));
+            tokens.extend(service::mock_service_impl(&service, true, true));
         }
         if let AsyncSetting::AsyncDefault = &self.async_setting {
             tokens.extend(service::service_trait(&service, true, false));
-            tokens.extend(service::reqwest_service_impl(&service, true, false));
+            tokens.extend(service::transport_service_impl(&service, true, false));
+            tokens.extend(service::mock_service_impl(&service, true, false));
         }
         tokens
     }
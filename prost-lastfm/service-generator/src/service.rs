@@ -1,5 +1,5 @@
 use convert_case::{Case, Casing};
-use extensions::LastFmIdent;
+use extensions::{LastFmHttpMethod, LastFmIdent};
 use proc_macro2::TokenStream;
 use quote::quote;
 use std::str::FromStr;
@@ -25,6 +25,23 @@ fn ext_auth(base: &prost_build::Method) -> LastFmIdent {
     LastFmIdent::from_i32(data).unwrap_or(LastFmIdent::IdentUnknown)
 }
 
+fn ext_http_method(base: &prost_build::Method) -> LastFmHttpMethod {
+    let data = *base
+        .options
+        .extension_set
+        .extension_data(extensions::HTTP_METHOD)
+        .unwrap_or(&0);
+    LastFmHttpMethod::from_i32(data).unwrap_or(LastFmHttpMethod::HttpMethodUnknown)
+}
+
+fn ext_max_batch(base: &prost_build::Method) -> Option<u32> {
+    base.options
+        .extension_set
+        .extension_data(extensions::MAX_BATCH)
+        .ok()
+        .copied()
+}
+
 fn ext_method_name(base: &prost_build::Method) -> String {
     base.options
         .extension_set
@@ -64,7 +81,7 @@ pub fn agent_trait(method: &prost_build::Method, write_async: bool) -> TokenStre
     }
 }
 
-pub fn reqwest_agent_impl(method: &prost_build::Method, write_async: bool) -> TokenStream {
+pub fn transport_agent_impl(method: &prost_build::Method, write_async: bool) -> TokenStream {
     let (token_async, token_await) = async_tokens(write_async);
 
     let name = TokenStream::from_str(&arg_name(&method.name)).unwrap();
@@ -88,40 +105,46 @@ pub fn reqwest_agent_impl(method: &prost_build::Method, write_async: bool) -> To
         }
     };
 
+    let http_method = ext_http_method(method);
+    let max_batch = match ext_max_batch(method) {
+        Some(max_batch) => {
+            let max_batch = proc_macro2::Literal::usize_unsuffixed(max_batch as usize);
+            quote!(#max_batch)
+        }
+        None => quote!(crate::pairs::DEFAULT_MAX_BATCH),
+    };
     let method = &ext_method_name(method);
-    let req_method = quote!(get);
-    let expect_msg = format!("invalid message type {}", name);
+
+    let api_call = quote! {
+        crate::api::ApiCall::new(
+            &self.api_key,
+            #method,
+            #session_key
+        )
+        .struct_params(#base_arg, #max_batch)
+        .map_err(crate::error::Error::from)?
+    };
+    let request = match http_method {
+        LastFmHttpMethod::HttpMethodUnknown | LastFmHttpMethod::HttpMethodGet => quote! {
+            let url = #api_call.to_url(self.secret.as_bytes(), &self.endpoint, #append_signature);
+            let request = crate::transport::Request::get(url);
+        },
+        LastFmHttpMethod::HttpMethodPost => quote! {
+            let (url, body) = #api_call.to_form(self.secret.as_bytes(), &self.endpoint, #append_signature);
+            let request = crate::transport::Request::post(url, body);
+        },
+    };
 
     quote! {
         #token_async fn #name(&self, #(#args),*) -> Result<#base_out_ty, Self::Error> {
-            let url = crate::api::ApiCall::new(
-                &self.api_key,
-                #method,
-                #session_key
-            )
-            .struct_params(#base_arg).expect(#expect_msg)
-            .to_url(self.secret.as_bytes(), &self.endpoint, #append_signature);
-            #[cfg(debug_assertions)]
-            let resp = {
-                let bytes = self.client.#req_method(url).send()
-                #token_await?
-                .bytes()
+            #request
+            let response = self.transport.send(request)
                 #token_await?;
-                println!("Data: {:?}", bytes);
-                serde_json::from_slice::<crate::api::Response<#base_out_ty>>(&bytes)
-                .map_err(|err| crate::error::LastFMError {
-                    message: err.to_string(),
-                    error: crate::error::ErrorCode::InvalidFormat,
-                })?.to_result()?
-            };
-            #[cfg(not(debug_assertions))]
-            let resp = {
-                self.client.#req_method(url).send()
-                #token_await?
-                .json::<crate::api::Response<#base_out_ty>>()
-                #token_await?
-                .to_result()?
-            };
+            let resp = crate::api::decode_response::<#base_out_ty>(
+                response.status,
+                response.retry_after,
+                &response.body,
+            )?;
             Ok(resp)
         }
     }
@@ -144,13 +167,15 @@ pub fn service_trait(
     };
     quote! {
         pub trait #name {
-            type Error: From<reqwest::Error> + From<crate::error::LastFMError>;
+            type Error: From<crate::error::Error>;
             #(#methods)*
         }
     }
 }
 
-pub fn reqwest_service_impl(
+/// The agent implementing a service over a transport, which defaults to
+/// reqwest's client.
+pub fn transport_service_impl(
     service: &prost_build::Service,
     write_async: bool,
     name_async: bool,
@@ -158,7 +183,7 @@ pub fn reqwest_service_impl(
     let methods = service
         .methods
         .iter()
-        .map(|method| reqwest_agent_impl(method, write_async))
+        .map(|method| transport_agent_impl(method, write_async))
         .collect::<Vec<_>>();
 
     let (name, agent) = if name_async {
@@ -172,24 +197,150 @@ pub fn reqwest_service_impl(
             TokenStream::from_str(&format!("{}Agent", &service.name)).unwrap(),
         )
     };
-    let client = if write_async {
-        quote!(reqwest::Client)
+    let (client, transport) = if write_async {
+        (quote!(reqwest::Client), quote!(crate::transport::AsyncTransport))
     } else {
-        quote!(reqwest::blocking::Client)
+        (quote!(reqwest::blocking::Client), quote!(crate::transport::Transport))
     };
     quote! {
-        pub struct #agent {
-            client: #client,
+        pub struct #agent<T = #client> {
+            transport: T,
             api_key: String,
             secret: String,
             endpoint: String,
         }
-        impl #agent {
-            pub fn new(client: #client, api_key: String, secret: String, endpoint: String) -> Self {
-                Self { client, api_key, secret, endpoint }
+        impl<T> #agent<T> {
+            pub fn new(transport: T, api_key: String, secret: String, endpoint: String) -> Self {
+                Self { transport, api_key, secret, endpoint }
+            }
+        }
+        impl<T: #transport> #name for #agent<T> {
+            type Error = crate::error::Error;
+            #(#methods)*
+        }
+    }
+}
+
+/// The response type of each method, for checking recorded fixtures.
+/// Shared by the sync and async traits.
+pub fn response_types(service: &prost_build::Service) -> TokenStream {
+    let name = TokenStream::from_str(&format!(
+        "{}_RESPONSES",
+        service.name.to_case(Case::UpperSnake)
+    ))
+    .unwrap();
+    let entries = service.methods.iter().map(|method| {
+        let method_name = ext_method_name(method);
+        let type_name = arg_type(&method.output_type);
+        let base_out_ty = TokenStream::from_str(&type_name).unwrap();
+        quote! {
+            crate::fixture::ResponseType {
+                method: #method_name,
+                name: #type_name,
+                decode: crate::fixture::decode::<#base_out_ty>,
+            }
+        }
+    });
+    let doc = format!(" The response type of each `{}` method.", service.name);
+    quote! {
+        #[doc = #doc]
+        pub const #name: &[crate::fixture::ResponseType] = &[#(#entries),*];
+    }
+}
+
+/// A mock's implementation of one method, which records the call and takes
+/// the next queued response.
+pub fn mock_agent_impl(method: &prost_build::Method, write_async: bool) -> TokenStream {
+    let (token_async, _) = async_tokens(write_async);
+
+    let name = TokenStream::from_str(&arg_name(&method.name)).unwrap();
+    let base_arg = TokenStream::from_str(&arg_name(&method.input_type)).unwrap();
+    let base_in_ty = TokenStream::from_str(&arg_type(&method.input_type)).unwrap();
+    let base_out_ty = TokenStream::from_str(&arg_type(&method.output_type)).unwrap();
+
+    let mut args = vec![quote!(#base_arg: #base_in_ty)];
+
+    let session_token = match ext_auth(&method) {
+        LastFmIdent::IdentUnknown
+        | LastFmIdent::IdentStandard
+        | LastFmIdent::IdentSignatureOnly => quote!(None),
+        LastFmIdent::IdentSessionToken => {
+            args.push(quote!(session_token: &str));
+            quote!(Some(session_token))
+        }
+        LastFmIdent::IdentSessionOptional => {
+            args.push(quote!(session_token: Option<&str>));
+            quote!(session_token)
+        }
+    };
+
+    quote! {
+        #token_async fn #name(&self, #(#args),*) -> Result<#base_out_ty, Self::Error> {
+            self.#name.call(#base_arg, #session_token)
+        }
+    }
+}
+
+/// A mock of a service for tests, with a public `MockMethod` per method.
+/// Shared by the sync and async traits.
+pub fn mock_service_struct(service: &prost_build::Service) -> TokenStream {
+    let mock = TokenStream::from_str(&format!("{}Mock", &service.name)).unwrap();
+    let names = service
+        .methods
+        .iter()
+        .map(|method| TokenStream::from_str(&arg_name(&method.name)).unwrap())
+        .collect::<Vec<_>>();
+    let types = service.methods.iter().map(|method| {
+        let base_in_ty = TokenStream::from_str(&arg_type(&method.input_type)).unwrap();
+        let base_out_ty = TokenStream::from_str(&arg_type(&method.output_type)).unwrap();
+        quote!(crate::mock::MockMethod<#base_in_ty, #base_out_ty>)
+    });
+    let method_names = service.methods.iter().map(ext_method_name);
+    quote! {
+        #[cfg(feature = "mock")]
+        pub struct #mock {
+            #(pub #names: #types,)*
+        }
+        #[cfg(feature = "mock")]
+        impl Default for #mock {
+            fn default() -> Self {
+                Self {
+                    #(#names: crate::mock::MockMethod::new(#method_names),)*
+                }
             }
         }
-        impl #name for #agent {
+        #[cfg(feature = "mock")]
+        impl #mock {
+            pub fn new() -> Self {
+                Self::default()
+            }
+            /// Checks that every method took all of its queued responses.
+            pub fn assert_done(&self) {
+                #(self.#names.assert_done();)*
+            }
+        }
+    }
+}
+
+pub fn mock_service_impl(
+    service: &prost_build::Service,
+    write_async: bool,
+    name_async: bool,
+) -> TokenStream {
+    let methods = service
+        .methods
+        .iter()
+        .map(|method| mock_agent_impl(method, write_async))
+        .collect::<Vec<_>>();
+    let name = if name_async {
+        TokenStream::from_str(&format!("{}Async", &service.name)).unwrap()
+    } else {
+        TokenStream::from_str(&service.name).unwrap()
+    };
+    let mock = TokenStream::from_str(&format!("{}Mock", &service.name)).unwrap();
+    quote! {
+        #[cfg(feature = "mock")]
+        impl #name for #mock {
             type Error = crate::error::Error;
             #(#methods)*
         }
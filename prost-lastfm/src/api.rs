@@ -1,21 +1,44 @@
-use crate::error::LastFMError;
+use crate::error::{DecodeError, Error, LastFMError, StatusError};
 use crate::pairs;
 use std::collections::HashMap;
+use std::time::Duration;
 
-#[derive(serde::Deserialize)]
-#[serde(untagged)]
-pub enum Response<T> {
-    LastFMError(LastFMError), // attempt error first
-    Valid(T),
+/// The seconds given by a `Retry-After` header. Dates are not supported.
+pub fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?;
+    value.trim().parse().ok().map(Duration::from_secs)
 }
 
-impl<T> Response<T> {
-    pub fn to_result(self) -> Result<T, LastFMError> {
-        match self {
-            Response::Valid(val) => Ok(val),
-            Response::LastFMError(err) => Err(err),
+/// Decodes a response. A Last.fm error in the body is checked for first, and
+/// wins over the HTTP status since Last.fm sends its errors with error
+/// statuses.
+pub fn decode_response<T>(
+    status: reqwest::StatusCode,
+    retry_after: Option<Duration>,
+    body: &[u8],
+) -> Result<T, Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    if let Ok(err) = serde_json::from_slice::<LastFMError>(body) {
+        return Err(LastFMError { retry_after, ..err }.into());
+    }
+    let body_text = || String::from_utf8_lossy(body).into_owned();
+    if !status.is_success() {
+        return Err(StatusError {
+            status,
+            retry_after,
+            body: body_text(),
         }
+        .into());
     }
+    serde_json::from_slice::<T>(body).map_err(|source| {
+        DecodeError {
+            source,
+            body: body_text(),
+        }
+        .into()
+    })
 }
 
 pub struct ApiCall {
@@ -40,16 +63,21 @@ impl ApiCall {
         Self { params, ..self }
     }
 
-    pub fn struct_params<T>(self, msg: T) -> Result<Self, pairs::InvalidStructError>
+    pub fn struct_params<T>(
+        self,
+        msg: T,
+        max_batch: usize,
+    ) -> Result<Self, pairs::InvalidStructError>
     where
         T: serde::Serialize,
     {
-        let pairs = pairs::to_pairs(msg)?;
+        let pairs = pairs::to_pairs(msg, max_batch)?;
         let mut params = self.params;
         params.extend(pairs.into_iter());
         Ok(Self { params, ..self })
     }
 
+    // Covers every parameter, including the indexed keys of batch calls.
     fn signature(&self, secret: &[u8]) -> String {
         let mut params = self.params.iter().collect::<Vec<_>>();
         params.sort_by(|(a, _), (b, _)| a.cmp(b));
@@ -62,23 +90,68 @@ impl ApiCall {
         format!("{:x}", md5::compute(prehash))
     }
 
+    fn into_pairs(self, secret: &[u8], append_signature: bool) -> Vec<(String, String)> {
+        let signature = append_signature.then(|| self.signature(secret));
+        let mut params = self.params.into_iter().collect::<Vec<_>>();
+        params.sort_by(|(a, _), (b, _)| a.cmp(b));
+        if let Some(signature) = signature {
+            params.push(("api_sig".to_string(), signature));
+        }
+        params.push(("format".to_string(), "json".to_string()));
+        params
+    }
+
     pub fn to_url(self, secret: &[u8], endpoint: &str, append_signature: bool) -> url::Url {
         let mut base = url::Url::parse(endpoint).unwrap();
-        {
-            let mut query = base.query_pairs_mut();
-            let mut params = self.params.iter().collect::<Vec<_>>();
-            params.sort_by(|(a, _), (b, _)| a.cmp(b));
-            for (key, value) in &params {
-                query.append_pair(key, value);
-            }
-            if append_signature {
-                let signature = self.signature(secret);
-                query.append_pair("api_sig", &signature);
-            }
-            query.append_pair("format", "json");
-        }
-        #[cfg(debug_assertions)]
-        println!("URL: {}", base);
+        let pairs = self.into_pairs(secret, append_signature);
+        base.query_pairs_mut().extend_pairs(pairs);
         base
     }
+
+    pub fn to_form(
+        self,
+        secret: &[u8],
+        endpoint: &str,
+        append_signature: bool,
+    ) -> (url::Url, String) {
+        let base = url::Url::parse(endpoint).unwrap();
+        let pairs = self.into_pairs(secret, append_signature);
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(pairs)
+            .finish();
+        (base, body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tracks;
+
+    // The md5 of "album[1]Selected Ambient Works 85-92api_keykeyartist[0]...
+    // track[1]Xtalsecret": every pair sorted by key, then the secret.
+    #[test]
+    fn signature_scrobble() {
+        let request = tracks::ScrobbleRequest {
+            scrobbles: vec![
+                tracks::Scrobble {
+                    artist: String::from("Boards of Canada"),
+                    track: String::from("Roygbiv"),
+                    timestamp: 1_700_000_000,
+                    ..Default::default()
+                },
+                tracks::Scrobble {
+                    artist: String::from("Aphex Twin"),
+                    track: String::from("Xtal"),
+                    timestamp: 1_700_000_300,
+                    album: Some(String::from("Selected Ambient Works 85-92")),
+                    ..Default::default()
+                },
+            ],
+        };
+        let call = ApiCall::new("key", "track.scrobble", Some("session"))
+            .struct_params(request, pairs::DEFAULT_MAX_BATCH)
+            .unwrap();
+        assert_eq!(call.signature(b"secret"), "37ba5ab1a1817e6e518d8482cda26ea6");
+    }
 }
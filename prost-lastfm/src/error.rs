@@ -4,14 +4,20 @@ mod proto {
     include!(concat!(env!("OUT_DIR"), "/lastfm.error.rs"));
 }
 
+pub use crate::pairs::InvalidStructError;
 pub use proto::error::Error as ErrorCode;
 use std::fmt::{Display, Formatter};
+use std::time::Duration;
 
 #[derive(Debug, serde::Deserialize)]
 pub struct LastFMError {
     pub message: String,
     #[serde(deserialize_with = "parse_code")]
     pub error: ErrorCode,
+    /// From the `Retry-After` header of the response, if it gave a number of
+    /// seconds. Last.fm sends it with rate limit errors.
+    #[serde(skip)]
+    pub retry_after: Option<Duration>,
 }
 
 fn parse_code<'de, D>(deserializer: D) -> Result<ErrorCode, D::Error>
@@ -24,22 +30,236 @@ where
     )
 }
 
+impl ErrorCode {
+    /// Whether Last.fm asks for the request to be tried again later.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ErrorCode::ServiceOffline
+                | ErrorCode::InternalError
+                | ErrorCode::TemporarilyUnavailable
+                | ErrorCode::RateLimitExceeded
+        )
+    }
+
+    /// Whether Last.fm rejected the credentials: the API key, the signature
+    /// made with the secret, or the session.
+    pub fn is_auth_failure(&self) -> bool {
+        matches!(
+            self,
+            ErrorCode::AuthenticationFailed
+                | ErrorCode::InvalidSessionKey
+                | ErrorCode::InvalidApiKey
+                | ErrorCode::InvalidMethodSignature
+                | ErrorCode::SuspendedApiKey
+        )
+    }
+}
+
 impl Display for LastFMError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}: {}", self.error, self.message)
     }
 }
 
+/// How much of a response body is shown in messages.
+const SHOWN_BODY: usize = 200;
+
+/// A response body as shown in messages: cut short, and with session keys
+/// left out, since errors end up in logs. The body itself is kept whole for
+/// callers.
+fn shown_body(body: &str) -> String {
+    let body = match serde_json::from_str::<serde_json::Value>(body) {
+        Ok(mut json) => {
+            redact_keys(&mut json);
+            json.to_string()
+        }
+        Err(_) => body.to_string(),
+    };
+    match body.char_indices().nth(SHOWN_BODY) {
+        Some((end, _)) => format!("{}... ({} bytes)", &body[..end], body.len()),
+        None => body,
+    }
+}
+
+fn redact_keys(json: &mut serde_json::Value) {
+    match json {
+        serde_json::Value::Object(members) => {
+            for (name, value) in members {
+                if name == "key" {
+                    *value = "[redacted]".into();
+                } else {
+                    redact_keys(value);
+                }
+            }
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(redact_keys),
+        _ => {}
+    }
+}
+
+/// An HTTP error status without a Last.fm error in the body.
+pub struct StatusError {
+    pub status: reqwest::StatusCode,
+    /// From the `Retry-After` header, if it gave a number of seconds.
+    pub retry_after: Option<Duration>,
+    pub body: String,
+}
+
+impl Display for StatusError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.status, shown_body(&self.body))
+    }
+}
+
+impl std::fmt::Debug for StatusError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StatusError")
+            .field("status", &self.status)
+            .field("retry_after", &self.retry_after)
+            .field("body", &shown_body(&self.body))
+            .finish()
+    }
+}
+
+/// A response body that is neither the expected message nor a Last.fm error.
+pub struct DecodeError {
+    pub source: serde_json::Error,
+    pub body: String,
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} in {}", self.source, shown_body(&self.body))
+    }
+}
+
+impl std::fmt::Debug for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DecodeError")
+            .field("source", &self.source)
+            .field("body", &shown_body(&self.body))
+            .finish()
+    }
+}
+
+/// A request that did not get a response.
+#[derive(Debug)]
+pub struct TransportError {
+    pub source: Box<dyn std::error::Error + Send + Sync>,
+    /// Whether sending again may work, as after a timeout or a refused
+    /// connection.
+    pub retryable: bool,
+    pub status: Option<reqwest::StatusCode>,
+}
+
+impl TransportError {
+    pub fn new(
+        source: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+        retryable: bool,
+    ) -> Self {
+        Self {
+            source: source.into(),
+            retryable,
+            status: None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for TransportError {
+    fn from(err: reqwest::Error) -> Self {
+        Self {
+            retryable: !err.is_builder() && !err.is_redirect(),
+            status: err.status(),
+            source: err.into(),
+        }
+    }
+}
+
+impl Display for TransportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
 #[derive(Debug, into_enum::IntoEnum)]
 pub enum Error {
-    Reqwest(reqwest::Error),
+    /// The request did not get a response.
+    Transport(TransportError),
+    Status(StatusError),
+    Decode(DecodeError),
+    /// The request message could not be turned into parameters.
+    Encode(InvalidStructError),
     LastFM(LastFMError),
 }
 
+impl Error {
+    /// The Last.fm error code, if Last.fm reported an error.
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            Error::LastFM(err) => Some(err.error),
+            _ => None,
+        }
+    }
+
+    /// The HTTP status of the response, if there was one and it was an error.
+    pub fn status(&self) -> Option<reqwest::StatusCode> {
+        match self {
+            Error::Transport(err) => err.status,
+            Error::Status(err) => Some(err.status),
+            _ => None,
+        }
+    }
+
+    /// Whether the same request may succeed later.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Transport(err) => err.retryable,
+            Error::Status(err) => {
+                err.status.is_server_error()
+                    || err.status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                    || err.status == reqwest::StatusCode::REQUEST_TIMEOUT
+            }
+            Error::Decode(_) | Error::Encode(_) => false,
+            Error::LastFM(err) => err.error.is_retryable(),
+        }
+    }
+
+    /// Whether the credentials were rejected, so retrying needs new ones.
+    pub fn is_auth_failure(&self) -> bool {
+        match self {
+            Error::Status(err) => {
+                err.status == reqwest::StatusCode::UNAUTHORIZED
+                    || err.status == reqwest::StatusCode::FORBIDDEN
+            }
+            Error::LastFM(err) => err.error.is_auth_failure(),
+            _ => false,
+        }
+    }
+
+    /// How long the server asked to wait before trying again.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::Status(err) => err.retry_after,
+            Error::LastFM(err) => err.retry_after,
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::Transport(err.into())
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Error::Reqwest(err) => write!(f, "Error(Reqwest) {{ {} }}", err),
+            Error::Transport(err) => write!(f, "Error(Transport) {{ {} }}", err),
+            Error::Status(err) => write!(f, "Error(Status) {{ {} }}", err),
+            Error::Decode(err) => write!(f, "Error(Decode) {{ {} }}", err),
+            Error::Encode(err) => write!(f, "Error(Encode) {{ {} }}", err),
             Error::LastFM(err) => write!(f, "Error(LastFM) {{ {} }}", err),
         }
     }
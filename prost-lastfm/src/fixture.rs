@@ -0,0 +1,294 @@
+use crate::error::{Error, TransportError};
+use crate::transport::{AsyncTransport, HttpMethod, Request, Response, Transport};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Parameters replaced before saving, since they give away the user's
+/// account or the application's key. Replay ignores them.
+const REDACTED_PARAMS: &[&str] = &["api_key", "api_sig", "sk", "password", "token", "authToken"];
+/// What redacted values are replaced with.
+pub const REDACTED: &str = "REDACTED";
+
+/// A recorded request and its response, saved as JSON.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Fixture {
+    /// The Last.fm method, such as `user.getRecentTracks`.
+    pub method: String,
+    pub http_method: HttpMethod,
+    /// From the query, or the body of a POST.
+    pub params: BTreeMap<String, String>,
+    pub status: u16,
+    /// The response body. A body that is not JSON is kept as a string.
+    pub body: serde_json::Value,
+}
+
+impl Fixture {
+    /// Builds a fixture with the secrets left out.
+    pub fn new(request: &Request, response: &Response) -> Self {
+        let params = redacted_params(request);
+        let mut body = serde_json::from_slice(&response.body).unwrap_or_else(|_| {
+            serde_json::Value::String(String::from_utf8_lossy(&response.body).into_owned())
+        });
+        // The session key is as good as the password.
+        if let Some(key) = body.pointer_mut("/session/key") {
+            *key = REDACTED.into();
+        }
+        Self {
+            method: params.get("method").cloned().unwrap_or_default(),
+            http_method: request.method,
+            params,
+            status: response.status.as_u16(),
+            body,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = std::fs::read(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        serde_json::from_slice(&data).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /// Loads every `.json` file in `dir`, in order of file name.
+    pub fn load_dir(dir: &Path) -> Result<Vec<(PathBuf, Self)>, String> {
+        let entries =
+            std::fs::read_dir(dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
+        let mut paths = entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+            .into_iter()
+            .map(|path| Ok((path.clone(), Self::load(&path)?)))
+            .collect()
+    }
+
+    /// Saves to `dir` as `<method>-<n>.json`, with the first free `n`.
+    pub fn save(&self, dir: &Path) -> std::io::Result<PathBuf> {
+        std::fs::create_dir_all(dir)?;
+        let mut data = serde_json::to_vec_pretty(self).map_err(std::io::Error::other)?;
+        data.push(b'\n');
+        for n in 0.. {
+            let path = dir.join(format!("{}-{:03}.json", self.method, n));
+            match std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(mut file) => {
+                    std::io::Write::write_all(&mut file, &data)?;
+                    return Ok(path);
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        unreachable!()
+    }
+
+    pub fn response(&self) -> Response {
+        let body = match &self.body {
+            serde_json::Value::String(text) => text.clone().into_bytes(),
+            json => json.to_string().into_bytes(),
+        };
+        Response {
+            status: reqwest::StatusCode::from_u16(self.status)
+                .unwrap_or(reqwest::StatusCode::INTERNAL_SERVER_ERROR),
+            retry_after: None,
+            body,
+        }
+    }
+
+    /// Whether `request` asks for what this fixture recorded. Redacted
+    /// parameters are not compared.
+    pub fn matches(&self, request: &Request) -> bool {
+        let unredacted = |params: &BTreeMap<String, String>| {
+            params
+                .iter()
+                .filter(|(key, _)| !REDACTED_PARAMS.contains(&key.as_str()))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect::<BTreeMap<_, _>>()
+        };
+        self.http_method == request.method
+            && unredacted(&self.params) == unredacted(&request_params(request))
+    }
+}
+
+fn request_params(request: &Request) -> BTreeMap<String, String> {
+    let mut params = request
+        .url
+        .query_pairs()
+        .into_owned()
+        .collect::<BTreeMap<_, _>>();
+    if let Some(body) = &request.body {
+        params.extend(url::form_urlencoded::parse(body.as_bytes()).into_owned());
+    }
+    params
+}
+
+fn redacted_params(request: &Request) -> BTreeMap<String, String> {
+    let mut params = request_params(request);
+    for (key, value) in params.iter_mut() {
+        if REDACTED_PARAMS.contains(&key.as_str()) {
+            *value = REDACTED.to_string();
+        }
+    }
+    params
+}
+
+/// Passes requests on to another transport and saves each exchange as a
+/// [`Fixture`] in a directory.
+pub struct Recorder<T> {
+    inner: T,
+    dir: PathBuf,
+}
+
+impl<T> Recorder<T> {
+    pub fn new(inner: T, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            dir: dir.into(),
+        }
+    }
+
+    fn save(&self, request: &Request, response: &Response) -> Result<(), Error> {
+        Fixture::new(request, response)
+            .save(&self.dir)
+            .map_err(|err| TransportError::new(format!("error saving fixture: {}", err), false))?;
+        Ok(())
+    }
+}
+
+impl<T: Transport> Transport for Recorder<T> {
+    fn send(&self, request: Request) -> Result<Response, Error> {
+        let response = self.inner.send(request.clone())?;
+        self.save(&request, &response)?;
+        Ok(response)
+    }
+}
+
+impl<T: AsyncTransport + Sync> AsyncTransport for Recorder<T> {
+    async fn send(&self, request: Request) -> Result<Response, Error> {
+        let response = self.inner.send(request.clone()).await?;
+        self.save(&request, &response)?;
+        Ok(response)
+    }
+}
+
+/// Answers requests from recorded fixtures, without any networking. When
+/// several fixtures match a request they are used in order, and the last
+/// one keeps answering.
+pub struct Replay {
+    fixtures: Mutex<Vec<Fixture>>,
+}
+
+impl Replay {
+    pub fn new(fixtures: Vec<Fixture>) -> Self {
+        Self {
+            fixtures: Mutex::new(fixtures),
+        }
+    }
+
+    /// Replays every fixture in `dir`.
+    pub fn open(dir: &Path) -> Result<Self, String> {
+        let fixtures = Fixture::load_dir(dir)?;
+        Ok(Self::new(
+            fixtures.into_iter().map(|(_, fixture)| fixture).collect(),
+        ))
+    }
+
+    fn answer(&self, request: &Request) -> Result<Response, Error> {
+        let mut fixtures = self.fixtures.lock().unwrap();
+        let matching = fixtures
+            .iter()
+            .enumerate()
+            .filter(|(_, fixture)| fixture.matches(request))
+            .map(|(index, _)| index)
+            .take(2)
+            .collect::<Vec<_>>();
+        match matching[..] {
+            [index, _] => Ok(fixtures.remove(index).response()),
+            [index] => Ok(fixtures[index].response()),
+            _ => Err(TransportError::new(
+                format!("no fixture for {:?}", redacted_params(request)),
+                false,
+            )
+            .into()),
+        }
+    }
+}
+
+impl Transport for Replay {
+    fn send(&self, request: Request) -> Result<Response, Error> {
+        self.answer(&request)
+    }
+}
+
+impl AsyncTransport for Replay {
+    async fn send(&self, request: Request) -> Result<Response, Error> {
+        self.answer(&request)
+    }
+}
+
+/// A method's response type, as listed by the generated `<SERVICE>_RESPONSES`
+/// constants.
+pub struct ResponseType {
+    pub method: &'static str,
+    pub name: &'static str,
+    pub decode: fn(&Response) -> Result<(), Error>,
+}
+
+#[doc(hidden)]
+pub fn decode<T: serde::de::DeserializeOwned>(response: &Response) -> Result<(), Error> {
+    crate::api::decode_response::<T>(response.status, response.retry_after, &response.body)
+        .map(drop)
+}
+
+/// Decodes every fixture in `dir` recorded from one of `responses`' methods
+/// with that method's response type. A Last.fm error counts as decoded.
+/// Panics listing every failure, or when no fixtures were checked.
+pub fn check_fixtures(dir: &Path, responses: &[ResponseType]) {
+    let fixtures = Fixture::load_dir(dir).unwrap_or_else(|err| panic!("{}", err));
+    let mut checked = 0;
+    let mut failures = Vec::new();
+    for response_type in responses {
+        let recorded = fixtures
+            .iter()
+            .filter(|(_, fixture)| fixture.method == response_type.method)
+            .collect::<Vec<_>>();
+        if recorded.is_empty() {
+            println!("No fixtures for {}", response_type.method);
+        }
+        for (path, fixture) in recorded {
+            checked += 1;
+            match (response_type.decode)(&fixture.response()) {
+                Ok(()) | Err(Error::LastFM(_)) => {}
+                Err(err) => failures.push(format!(
+                    "{} as {}: {}",
+                    path.display(),
+                    response_type.name,
+                    err
+                )),
+            }
+        }
+    }
+    assert!(checked > 0, "no fixtures in {}", dir.display());
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+/// Declares a test per group of response types that checks them against the
+/// fixtures in a directory, as in
+/// `fixture_tests!("tests/fixtures" => auth: prost_lastfm::AUTH_SERVICE_RESPONSES)`.
+/// Attributes before a name, such as `#[ignore]`, go on its test.
+#[macro_export]
+macro_rules! fixture_tests {
+    ($dir:expr => $($(#[$meta:meta])* $name:ident: $responses:expr),+ $(,)?) => {
+        $(
+            #[test]
+            $(#[$meta])*
+            fn $name() {
+                $crate::fixture::check_fixtures(::std::path::Path::new($dir), $responses);
+            }
+        )+
+    };
+}
@@ -5,10 +5,20 @@ mod shim;
 use serde_macros;
 
 pub mod error;
+pub mod fixture;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(feature = "gen_sync")]
+pub mod queue;
+pub mod transport;
 
 pub mod auth {
     include!(concat!(env!("OUT_DIR"), "/lastfm.auth.rs"));
 }
+// Not `track`, which holds the nested types of `Track`.
+pub mod tracks {
+    include!(concat!(env!("OUT_DIR"), "/lastfm.tracks.rs"));
+}
 pub mod user {
     include!(concat!(env!("OUT_DIR"), "/lastfm.user.rs"));
 }
@@ -0,0 +1,93 @@
+use crate::error::Error;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// A call a mock received.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Call<Req> {
+    pub request: Req,
+    /// The session key, for methods that take one.
+    pub session_token: Option<String>,
+}
+
+/// One method of a generated mock. Responses are queued ahead of the calls
+/// that take them, in order, and a call without a queued response panics.
+pub struct MockMethod<Req, Resp> {
+    name: &'static str,
+    responses: Mutex<VecDeque<Result<Resp, Error>>>,
+    calls: Mutex<Vec<Call<Req>>>,
+}
+
+impl<Req, Resp> MockMethod<Req, Resp> {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            responses: Mutex::new(VecDeque::new()),
+            calls: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The Last.fm method name, such as `user.getRecentTracks`.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn push(&self, response: Result<Resp, Error>) -> &Self {
+        self.responses.lock().unwrap().push_back(response);
+        self
+    }
+
+    pub fn push_ok(&self, response: Resp) -> &Self {
+        self.push(Ok(response))
+    }
+
+    pub fn push_err(&self, err: impl Into<Error>) -> &Self {
+        self.push(Err(err.into()))
+    }
+
+    pub fn call_count(&self) -> usize {
+        self.calls.lock().unwrap().len()
+    }
+
+    /// Removes and returns the calls so far.
+    pub fn take_calls(&self) -> Vec<Call<Req>> {
+        std::mem::take(&mut *self.calls.lock().unwrap())
+    }
+
+    /// The calls so far.
+    pub fn calls(&self) -> Vec<Call<Req>>
+    where
+        Req: Clone,
+    {
+        self.calls.lock().unwrap().clone()
+    }
+
+    pub fn assert_called(&self, times: usize) {
+        let count = self.call_count();
+        assert_eq!(
+            count, times,
+            "{} was called {} times, expected {}",
+            self.name, count, times
+        );
+    }
+
+    pub fn assert_not_called(&self) {
+        self.assert_called(0);
+    }
+
+    /// Checks that every queued response was taken.
+    pub fn assert_done(&self) {
+        let left = self.responses.lock().unwrap().len();
+        assert_eq!(left, 0, "{} has {} responses left", self.name, left);
+    }
+
+    #[doc(hidden)]
+    pub fn call(&self, request: Req, session_token: Option<&str>) -> Result<Resp, Error> {
+        self.calls.lock().unwrap().push(Call {
+            request,
+            session_token: session_token.map(str::to_string),
+        });
+        let response = self.responses.lock().unwrap().pop_front();
+        response.unwrap_or_else(|| panic!("{} was called with no response queued", self.name))
+    }
+}
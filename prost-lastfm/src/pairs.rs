@@ -25,8 +25,19 @@ impl serde::ser::Error for InvalidStructError {
     }
 }
 
-pub fn to_pairs<T: Serialize>(val: T) -> Result<Vec<(String, String)>, InvalidStructError> {
-    val.serialize(KeyValueSerializer)
+/// Last.fm accepts at most 50 entries in a batch call such as `track.scrobble`.
+pub const DEFAULT_MAX_BATCH: usize = 50;
+
+/// Flattens a struct into key-value pairs.
+///
+/// A sequence of structs is flattened into indexed keys, so a field
+/// `scrobbles: [{artist: "a"}, {artist: "b"}]` becomes `artist[0]=a` and
+/// `artist[1]=b`. Sequences longer than `max_batch` are rejected.
+pub fn to_pairs<T: Serialize>(
+    val: T,
+    max_batch: usize,
+) -> Result<Vec<(String, String)>, InvalidStructError> {
+    val.serialize(KeyValueSerializer { max_batch })
 }
 
 macro_rules! string_serialize {
@@ -51,7 +62,7 @@ impl<'s> serde::Serializer for FlatSerializer {
     type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
 
     fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
-        Ok((if v { "0" } else { "1" }).to_string())
+        Ok((if v { "1" } else { "0" }).to_string())
     }
 
     string_serialize!(serialize_i8, i8);
@@ -200,7 +211,9 @@ macro_rules! err_serialize {
     };
 }
 
-struct KeyValueSerializer;
+struct KeyValueSerializer {
+    max_batch: usize,
+}
 
 impl serde::Serializer for KeyValueSerializer {
     type Ok = Vec<(String, String)>;
@@ -310,7 +323,7 @@ impl serde::Serializer for KeyValueSerializer {
     }
 
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
-        Ok(KeyValueDataSerializer::default())
+        Ok(KeyValueDataSerializer::new(self.max_batch))
     }
 
     fn serialize_struct(
@@ -318,7 +331,7 @@ impl serde::Serializer for KeyValueSerializer {
         _name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
-        Ok(KeyValueDataSerializer::default())
+        Ok(KeyValueDataSerializer::new(self.max_batch))
     }
 
     fn serialize_struct_variant(
@@ -334,9 +347,18 @@ impl serde::Serializer for KeyValueSerializer {
     }
 }
 
-#[derive(Default)]
 struct KeyValueDataSerializer {
     pairs: Vec<(String, String)>,
+    max_batch: usize,
+}
+
+impl KeyValueDataSerializer {
+    fn new(max_batch: usize) -> Self {
+        Self {
+            pairs: Vec::new(),
+            max_batch,
+        }
+    }
 }
 
 impl<'s> serde::ser::SerializeMap for KeyValueDataSerializer {
@@ -384,10 +406,216 @@ impl<'s> serde::ser::SerializeStruct for KeyValueDataSerializer {
     where
         T: ?Sized + Serialize,
     {
-        let value = value.serialize(FlatSerializer)?;
-        if value != "" {
-            self.pairs.push((key.to_string(), value));
+        self.pairs.extend(value.serialize(FieldSerializer {
+            key,
+            max_batch: self.max_batch,
+        })?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.pairs)
+    }
+}
+
+macro_rules! field_serialize {
+    ($fn:ident, $ty:ty) => {
+        fn $fn(self, v: $ty) -> Result<Self::Ok, Self::Error> {
+            self.flat(FlatSerializer.$fn(v)?)
+        }
+    };
+}
+
+/// Serializes a single struct field, which may expand into indexed pairs.
+struct FieldSerializer {
+    key: &'static str,
+    max_batch: usize,
+}
+
+impl FieldSerializer {
+    fn flat(self, value: String) -> Result<Vec<(String, String)>, InvalidStructError> {
+        if value.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(vec![(self.key.to_string(), value)])
+    }
+}
+
+impl serde::Serializer for FieldSerializer {
+    type Ok = Vec<(String, String)>;
+    type Error = InvalidStructError;
+    type SerializeSeq = IndexedSerializer;
+    type SerializeTuple = Impossible<Self::Ok, Self::Error>;
+    type SerializeTupleStruct = Impossible<Self::Ok, Self::Error>;
+    type SerializeTupleVariant = Impossible<Self::Ok, Self::Error>;
+    type SerializeMap = Impossible<Self::Ok, Self::Error>;
+    type SerializeStruct = Impossible<Self::Ok, Self::Error>;
+    type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
+
+    field_serialize!(serialize_bool, bool);
+    field_serialize!(serialize_i8, i8);
+    field_serialize!(serialize_i16, i16);
+    field_serialize!(serialize_i32, i32);
+    field_serialize!(serialize_i64, i64);
+    field_serialize!(serialize_u8, u8);
+    field_serialize!(serialize_u16, u16);
+    field_serialize!(serialize_u32, u32);
+    field_serialize!(serialize_u64, u64);
+    field_serialize!(serialize_f32, f32);
+    field_serialize!(serialize_f64, f64);
+    field_serialize!(serialize_char, char);
+    field_serialize!(serialize_str, &str);
+    field_serialize!(serialize_bytes, &[u8]);
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        self.flat(FlatSerializer.serialize_none()?)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        self.flat(FlatSerializer.serialize_unit()?)
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Self::Ok, Self::Error> {
+        self.flat(FlatSerializer.serialize_unit_struct(name)?)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        self.flat(FlatSerializer.serialize_unit_variant(name, variant_index, variant)?)
+    }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.flat(FlatSerializer.serialize_newtype_variant(name, variant_index, variant, value)?)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        if self.max_batch == 0 {
+            return Err(InvalidStructError("cannot serialize sequence".to_string()));
         }
+        if let Some(len) = len {
+            if len > self.max_batch {
+                return Err(InvalidStructError(format!(
+                    "sequence {} has {} entries, more than the maximum of {}",
+                    self.key, len, self.max_batch
+                )));
+            }
+        }
+        Ok(IndexedSerializer {
+            key: self.key,
+            max_batch: self.max_batch,
+            index: 0,
+            pairs: Vec::new(),
+        })
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Err(InvalidStructError("cannot serialize tuple".to_string()))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Err(InvalidStructError(
+            "cannot serialize tuple struct".to_string(),
+        ))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Err(InvalidStructError("cannot serialize sequence".to_string()))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Err(InvalidStructError("cannot serialize map".to_string()))
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Err(InvalidStructError("cannot serialize struct".to_string()))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Err(InvalidStructError("cannot serialize struct".to_string()))
+    }
+}
+
+/// Flattens each element of a sequence into pairs with an `[index]` suffix.
+struct IndexedSerializer {
+    key: &'static str,
+    max_batch: usize,
+    index: usize,
+    pairs: Vec<(String, String)>,
+}
+
+impl serde::ser::SerializeSeq for IndexedSerializer {
+    type Ok = Vec<(String, String)>;
+    type Error = InvalidStructError;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        if self.index >= self.max_batch {
+            return Err(InvalidStructError(format!(
+                "sequence {} has more than the maximum of {} entries",
+                self.key, self.max_batch
+            )));
+        }
+        // Elements may not hold sequences of their own.
+        let pairs = value.serialize(KeyValueSerializer { max_batch: 0 })?;
+        self.pairs.extend(
+            pairs
+                .into_iter()
+                .map(|(key, value)| (format!("{}[{}]", key, self.index), value)),
+        );
+        self.index += 1;
         Ok(())
     }
 
@@ -395,3 +623,107 @@ impl<'s> serde::ser::SerializeStruct for KeyValueDataSerializer {
         Ok(self.pairs)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs<T: Serialize>(val: T) -> Vec<(String, String)> {
+        to_pairs(val, DEFAULT_MAX_BATCH).unwrap()
+    }
+
+    fn owned(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[derive(Serialize)]
+    struct Flags {
+        chosen_by_user: bool,
+        extended: Option<bool>,
+    }
+
+    // Last.fm reads booleans as 1 and 0.
+    #[test]
+    fn pairs_bool() {
+        let flags = Flags {
+            chosen_by_user: true,
+            extended: Some(false),
+        };
+        assert_eq!(
+            pairs(flags),
+            owned(&[("chosen_by_user", "1"), ("extended", "0")])
+        );
+    }
+
+    #[derive(Serialize)]
+    struct Entry {
+        artist: String,
+        album: Option<String>,
+    }
+
+    #[derive(Serialize)]
+    struct Batch {
+        method: String,
+        entries: Vec<Entry>,
+    }
+
+    fn batch(len: usize) -> Batch {
+        Batch {
+            method: String::from("track.scrobble"),
+            entries: (0..len)
+                .map(|i| Entry {
+                    artist: format!("artist {}", i),
+                    album: (i == 1).then(|| String::from("album")),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn pairs_indexed() {
+        assert_eq!(
+            pairs(batch(2)),
+            owned(&[
+                ("method", "track.scrobble"),
+                ("artist[0]", "artist 0"),
+                ("artist[1]", "artist 1"),
+                ("album[1]", "album"),
+            ])
+        );
+    }
+
+    #[test]
+    fn pairs_over_limit() {
+        assert_eq!(to_pairs(batch(2), 2).unwrap().len(), 4);
+        let err = to_pairs(batch(3), 2).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "sequence entries has 3 entries, more than the maximum of 2"
+        );
+        assert!(to_pairs(batch(1), 0).is_err());
+    }
+
+    #[derive(Serialize)]
+    struct Tagged {
+        tags: Vec<String>,
+    }
+
+    #[derive(Serialize)]
+    struct Nested {
+        entries: Vec<Tagged>,
+    }
+
+    #[test]
+    fn pairs_nested() {
+        let nested = Nested {
+            entries: vec![Tagged {
+                tags: vec![String::from("ambient")],
+            }],
+        };
+        let err = to_pairs(nested, DEFAULT_MAX_BATCH).unwrap_err();
+        assert_eq!(err.to_string(), "cannot serialize sequence");
+    }
+}
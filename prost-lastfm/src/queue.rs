@@ -0,0 +1,178 @@
+use crate::error::Error;
+use crate::{LastFmService, tracks};
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, into_enum::IntoEnum)]
+pub enum QueueError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+}
+
+impl Display for QueueError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueueError::Io(err) => write!(f, "QueueError(Io) {{ {} }}", err),
+            QueueError::Json(err) => write!(f, "QueueError(Json) {{ {} }}", err),
+        }
+    }
+}
+
+impl std::error::Error for QueueError {}
+
+/// Outcome of [`ScrobbleQueue::flush`].
+#[derive(Debug, Default)]
+pub struct FlushReport {
+    /// Scrobbles Last.fm accepted.
+    pub accepted: u32,
+    /// Scrobbles Last.fm received but ignored, such as timestamps that are
+    /// too old.
+    pub ignored: u32,
+    /// Scrobbles discarded because Last.fm rejected them with an error that
+    /// will not go away on retry.
+    pub dropped: usize,
+    /// Scrobbles sent in a batch whose response could not be read. Last.fm
+    /// answered with a success status, so they are taken as sent rather than
+    /// sent twice, but whether they were accepted is unknown.
+    pub unconfirmed: usize,
+    /// The error that stopped the flush. The batch that caused it is still
+    /// queued.
+    pub error: Option<Error>,
+}
+
+/// Scrobbles waiting to be submitted, persisted to a JSON file so they survive
+/// restarts and network outages.
+///
+/// Every change is written to disk before the call returns.
+pub struct ScrobbleQueue {
+    path: PathBuf,
+    pending: VecDeque<tracks::Scrobble>,
+    max_batch: usize,
+}
+
+impl ScrobbleQueue {
+    /// Opens the queue stored at `path`, or an empty queue if it does not exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, QueueError> {
+        let path = path.as_ref().to_path_buf();
+        let pending = match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => VecDeque::new(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Self {
+            path,
+            pending,
+            max_batch: crate::pairs::DEFAULT_MAX_BATCH,
+        })
+    }
+
+    /// Sets the number of scrobbles submitted per request. Values above the
+    /// Last.fm limit of 50 are clamped.
+    pub fn with_max_batch(self, max_batch: usize) -> Self {
+        Self {
+            max_batch: max_batch.clamp(1, crate::pairs::DEFAULT_MAX_BATCH),
+            ..self
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn pending(&self) -> impl Iterator<Item = &tracks::Scrobble> {
+        self.pending.iter()
+    }
+
+    pub fn push(&mut self, scrobble: tracks::Scrobble) -> Result<(), QueueError> {
+        self.pending.push_back(scrobble);
+        self.save()
+    }
+
+    /// Submits queued scrobbles in batches, oldest first.
+    ///
+    /// A batch is removed once Last.fm responds to it, even when the response
+    /// cannot be decoded. Errors that may clear
+    /// up, and rejected credentials, stop the flush and keep the batch
+    /// queued. A batch Last.fm or the encoder rejects for its content is split
+    /// in half and sent again, until the scrobble at fault is sent alone and
+    /// dropped, so one bad entry does not take the rest of its batch with it.
+    pub fn flush<S>(&mut self, agent: &S, session_token: &str) -> Result<FlushReport, QueueError>
+    where
+        S: LastFmService<Error = Error>,
+    {
+        let mut report = FlushReport::default();
+        let mut limit = self.max_batch;
+        while !self.pending.is_empty() {
+            let count = self.pending.len().min(limit);
+            let request = tracks::ScrobbleRequest {
+                scrobbles: self.pending.range(..count).cloned().collect(),
+            };
+            match agent.track_scrobble(request, session_token) {
+                Ok(response) => {
+                    if let Some(tracks::scrobbles::Attributes { accepted, ignored }) =
+                        response.scrobbles.and_then(|scrobbles| scrobbles.attr)
+                    {
+                        report.accepted += accepted;
+                        report.ignored += ignored;
+                    }
+                }
+                // Only a response with a success status gets this far.
+                Err(Error::Decode(_)) => report.unconfirmed += count,
+                Err(err @ (Error::LastFM(_) | Error::Encode(_)))
+                    if !err.is_retryable() && !err.is_auth_failure() =>
+                {
+                    if count > 1 {
+                        limit = count / 2;
+                        continue;
+                    }
+                    report.dropped += 1;
+                    limit = self.max_batch;
+                }
+                Err(err) => {
+                    report.error = Some(err);
+                    break;
+                }
+            }
+            self.pending.drain(..count);
+            self.save()?;
+        }
+        Ok(report)
+    }
+
+    /// Writes the pending scrobbles to the queue file. Every change is already
+    /// saved as it is made; this is for making sure before exiting.
+    ///
+    /// The file is replaced whole through a rename, and both the new file and
+    /// the directory are synced, so a crash leaves either the old queue or the
+    /// new one.
+    pub fn save(&self) -> Result<(), QueueError> {
+        let data = serde_json::to_vec(&self.pending)?;
+        let temp_path = self.path.with_extension("tmp");
+        let mut file = std::fs::File::create(&temp_path)?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+        drop(file);
+        std::fs::rename(&temp_path, &self.path)?;
+        // Directories can only be opened for syncing on Unix.
+        #[cfg(unix)]
+        if let Some(dir) = self.path.parent() {
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            std::fs::File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+}
@@ -17,20 +17,59 @@ impl serde::de::Visitor<'_> for BoolVisitor {
             _ => Err(E::invalid_value(serde::de::Unexpected::Str(v), &self)),
         }
     }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(v)
+    }
+}
+
+// Maps null to None so values written by the Serialize impls read back.
+struct OptionVisitor<V>(V);
+
+impl<'de, V: serde::de::Visitor<'de>> serde::de::Visitor<'de> for OptionVisitor<V> {
+    type Value = Option<V::Value>;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.0.expecting(formatter)
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(None)
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(None)
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self.0).map(Some)
+    }
 }
 
 pub fn parse_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
 where
     D: serde::de::Deserializer<'de>,
 {
-    deserializer.deserialize_str(BoolVisitor)
+    deserializer.deserialize_any(BoolVisitor)
 }
 
 pub fn parse_option_bool<'de, D>(deserializer: D) -> Result<Option<bool>, D::Error>
 where
     D: serde::de::Deserializer<'de>,
 {
-    Ok(Some(parse_bool(deserializer)?))
+    deserializer.deserialize_option(OptionVisitor(BoolVisitor))
 }
 
 struct ParseVisitor<T> {
@@ -51,6 +90,25 @@ impl<T: std::str::FromStr> serde::de::Visitor<'_> for ParseVisitor<T> {
         v.parse::<T>()
             .map_err(|_| E::invalid_value(serde::de::Unexpected::Str(v), &self))
     }
+
+    // Some endpoints (track.scrobble) send bare numbers where others send strings.
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        v.to_string()
+            .parse::<T>()
+            .map_err(|_| E::invalid_value(serde::de::Unexpected::Unsigned(v), &self))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        v.to_string()
+            .parse::<T>()
+            .map_err(|_| E::invalid_value(serde::de::Unexpected::Signed(v), &self))
+    }
 }
 
 pub fn parse_from_string<'de, T, D>(deserializer: D) -> Result<T, D::Error>
@@ -58,7 +116,7 @@ where
     T: std::str::FromStr,
     D: serde::de::Deserializer<'de>,
 {
-    deserializer.deserialize_str(ParseVisitor {
+    deserializer.deserialize_any(ParseVisitor {
         _marker: std::marker::PhantomData,
     })
 }
@@ -68,5 +126,27 @@ where
     T: std::str::FromStr,
     D: serde::de::Deserializer<'de>,
 {
-    Ok(Some(parse_from_string(deserializer)?))
+    deserializer.deserialize_option(OptionVisitor(ParseVisitor {
+        _marker: std::marker::PhantomData,
+    }))
+}
+
+pub fn one_or_many<'de, T, D>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    T: serde::Deserialize<'de>,
+    D: serde::de::Deserializer<'de>,
+{
+    // Lists holding a single item are sent as the bare item.
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        Many(Vec<T>),
+        One(T),
+    }
+    Ok(
+        match <OneOrMany<T> as serde::Deserialize>::deserialize(deserializer)? {
+            OneOrMany::Many(items) => items,
+            OneOrMany::One(item) => vec![item],
+        },
+    )
 }
@@ -0,0 +1,131 @@
+use crate::error::{Error, TransportError};
+use std::future::Future;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HttpMethod {
+    Get,
+    Post,
+}
+
+/// A signed request. The parameters are in the URL's query, or in a form
+/// encoded body for a POST.
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: HttpMethod,
+    pub url: url::Url,
+    pub body: Option<String>,
+}
+
+impl Request {
+    pub fn get(url: url::Url) -> Self {
+        Self {
+            method: HttpMethod::Get,
+            url,
+            body: None,
+        }
+    }
+
+    pub fn post(url: url::Url, body: String) -> Self {
+        Self {
+            method: HttpMethod::Post,
+            url,
+            body: Some(body),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Response {
+    pub status: reqwest::StatusCode,
+    /// From the `Retry-After` header, if it gave a number of seconds.
+    pub retry_after: Option<Duration>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// A 200 response with `body`.
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status: reqwest::StatusCode::OK,
+            retry_after: None,
+            body: body.into(),
+        }
+    }
+}
+
+/// What the generated agents send requests through. The agents sign requests
+/// and decode responses, so a transport only moves bytes.
+pub trait Transport {
+    /// Sends `request`. Error statuses are responses, not errors.
+    fn send(&self, request: Request) -> Result<Response, Error>;
+}
+
+/// [`Transport`] for the async agents.
+pub trait AsyncTransport {
+    /// Sends `request`. Error statuses are responses, not errors.
+    fn send(&self, request: Request) -> impl Future<Output = Result<Response, Error>> + Send;
+}
+
+impl<T: Transport + ?Sized> Transport for &T {
+    fn send(&self, request: Request) -> Result<Response, Error> {
+        (**self).send(request)
+    }
+}
+
+#[cfg(feature = "gen_sync")]
+impl Transport for reqwest::blocking::Client {
+    fn send(&self, request: Request) -> Result<Response, Error> {
+        let response = match request.method {
+            HttpMethod::Get => self.get(request.url),
+            HttpMethod::Post => self
+                .post(request.url)
+                .header(
+                    reqwest::header::CONTENT_TYPE,
+                    "application/x-www-form-urlencoded",
+                )
+                .body(request.body.unwrap_or_default()),
+        }
+        .send()
+        .map_err(TransportError::from)?;
+        let status = response.status();
+        let retry_after = crate::api::retry_after(response.headers());
+        let body = response.bytes().map_err(TransportError::from)?.to_vec();
+        Ok(Response {
+            status,
+            retry_after,
+            body,
+        })
+    }
+}
+
+impl AsyncTransport for reqwest::Client {
+    async fn send(&self, request: Request) -> Result<Response, Error> {
+        let response = match request.method {
+            HttpMethod::Get => self.get(request.url),
+            HttpMethod::Post => self
+                .post(request.url)
+                .header(
+                    reqwest::header::CONTENT_TYPE,
+                    "application/x-www-form-urlencoded",
+                )
+                .body(request.body.unwrap_or_default()),
+        }
+        .send()
+        .await
+        .map_err(TransportError::from)?;
+        let status = response.status();
+        let retry_after = crate::api::retry_after(response.headers());
+        let body = response
+            .bytes()
+            .await
+            .map_err(TransportError::from)?
+            .to_vec();
+        Ok(Response {
+            status,
+            retry_after,
+            body,
+        })
+    }
+}
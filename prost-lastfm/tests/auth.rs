@@ -0,0 +1,85 @@
+use prost_lastfm::{AuthService, AuthServiceAgent, auth};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+
+/// A request as the server saw it.
+struct Request {
+    method: String,
+    target: String,
+    body: String,
+}
+
+/// Serves one canned JSON response and reports the request. Returns the
+/// endpoint.
+fn serve_once(body: &'static str) -> (String, mpsc::Receiver<Request>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}/2.0/", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+        let mut content_length = 0;
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+            if let Some((name, value)) = line.split_once(':')
+                && name.eq_ignore_ascii_case("content-length")
+            {
+                content_length = value.trim().parse().unwrap();
+            }
+            line.clear();
+        }
+        let mut request_body = vec![0; content_length];
+        reader.read_exact(&mut request_body).unwrap();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        reader.get_mut().write_all(response.as_bytes()).unwrap();
+        let mut parts = request_line.split_whitespace();
+        sender
+            .send(Request {
+                method: parts.next().unwrap().to_string(),
+                target: parts.next().unwrap().to_string(),
+                body: String::from_utf8(request_body).unwrap(),
+            })
+            .unwrap();
+    });
+    (endpoint, receiver)
+}
+
+#[test]
+fn mobile_session_posts_signed_credentials() {
+    let (endpoint, requests) =
+        serve_once(r#"{"session":{"name":"someone","key":"session-key","subscriber":0}}"#);
+    let agent = AuthServiceAgent::new(
+        reqwest::blocking::Client::new(),
+        String::from("key"),
+        String::from("secret"),
+        endpoint,
+    );
+    let response = agent
+        .auth_get_mobile_session(auth::GetMobileSessionRequest {
+            username: String::from("someone"),
+            password: String::from("p&ss word"),
+        })
+        .unwrap();
+    assert_eq!(response.session.unwrap().key, "session-key");
+
+    let request = requests.recv().unwrap();
+    assert_eq!(request.method, "POST");
+    // The password stays out of the URL, which ends up in logs.
+    assert_eq!(request.target, "/2.0/");
+    let params = url::form_urlencoded::parse(request.body.as_bytes())
+        .into_owned()
+        .collect::<HashMap<_, _>>();
+    assert_eq!(params["method"], "auth.getMobileSession");
+    assert_eq!(params["username"], "someone");
+    assert_eq!(params["password"], "p&ss word");
+    let prehash = "api_keykeymethodauth.getMobileSessionpasswordp&ss wordusernamesomeonesecret";
+    assert_eq!(params["api_sig"], format!("{:x}", md5::compute(prehash)));
+}
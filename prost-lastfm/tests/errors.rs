@@ -0,0 +1,139 @@
+use prost_lastfm::error::{Error, ErrorCode};
+use prost_lastfm::{LastFmService, LastFmServiceAgent, tracks, user};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::time::Duration;
+
+/// Serves one canned HTTP response, then stops. Returns the endpoint.
+fn serve_once(status: &str, headers: &[&str], body: &str) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}/2.0/", listener.local_addr().unwrap());
+    let mut response = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\n", status, body.len());
+    for header in headers {
+        response.push_str(header);
+        response.push_str("\r\n");
+    }
+    response.push_str("Connection: close\r\n\r\n");
+    response.push_str(body);
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+            line.clear();
+        }
+        reader.get_mut().write_all(response.as_bytes()).unwrap();
+    });
+    endpoint
+}
+
+fn agent(endpoint: String) -> LastFmServiceAgent {
+    LastFmServiceAgent::new(
+        reqwest::blocking::Client::new(),
+        String::from("key"),
+        String::from("secret"),
+        endpoint,
+    )
+}
+
+fn recent_tracks(endpoint: String) -> Result<user::GetRecentTracksResponse, Error> {
+    agent(endpoint).user_get_recent_tracks(user::GetRecentTracksRequest::default(), None)
+}
+
+#[test]
+fn error_status() {
+    let endpoint = serve_once(
+        "503 Service Unavailable",
+        &["Retry-After: 120", "Content-Type: text/html"],
+        "<html>down for maintenance</html>",
+    );
+    let err = recent_tracks(endpoint).unwrap_err();
+    let Error::Status(status) = &err else {
+        panic!("expected a status error, got {}", err);
+    };
+    assert_eq!(status.body, "<html>down for maintenance</html>");
+    assert_eq!(err.status(), Some(reqwest::StatusCode::SERVICE_UNAVAILABLE));
+    assert_eq!(err.retry_after(), Some(Duration::from_secs(120)));
+    assert!(err.is_retryable());
+    assert!(!err.is_auth_failure());
+    assert_eq!(err.code(), None);
+}
+
+#[test]
+fn error_api_over_status() {
+    let endpoint = serve_once(
+        "403 Forbidden",
+        &["Content-Type: application/json"],
+        r#"{"message":"Invalid session key - Please re-authenticate","error":9}"#,
+    );
+    let err = recent_tracks(endpoint).unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::InvalidSessionKey));
+    assert!(err.is_auth_failure());
+    assert!(!err.is_retryable());
+
+    let endpoint = serve_once(
+        "429 Too Many Requests",
+        &["Retry-After: 60"],
+        r#"{"message":"Rate Limit Exceeded","error":29}"#,
+    );
+    let err = recent_tracks(endpoint).unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::RateLimitExceeded));
+    assert_eq!(err.retry_after(), Some(Duration::from_secs(60)));
+    assert!(err.is_retryable());
+}
+
+#[test]
+fn error_decode() {
+    let endpoint = serve_once("200 OK", &[], "not json");
+    let err = recent_tracks(endpoint).unwrap_err();
+    let Error::Decode(decode) = &err else {
+        panic!("expected a decode error, got {}", err);
+    };
+    assert_eq!(decode.body, "not json");
+    assert!(!err.is_retryable());
+}
+
+#[test]
+fn error_body_shown_short() {
+    let body = r#"{"session":{"name":"someone","key":"d580d57f32848f5dcf574d1ce18d78b2"},"recenttracks":"none"}"#;
+    let err = recent_tracks(serve_once("200 OK", &[], body)).unwrap_err();
+    let Error::Decode(decode) = &err else {
+        panic!("expected a decode error, got {}", err);
+    };
+    // Callers still get the whole body.
+    assert_eq!(decode.body, body);
+    for shown in [err.to_string(), format!("{:?}", err)] {
+        assert!(!shown.contains("d580d57f"), "{}", shown);
+        assert!(shown.contains("someone"), "{}", shown);
+    }
+
+    let body = "x".repeat(10_000);
+    let err = recent_tracks(serve_once("502 Bad Gateway", &[], &body)).unwrap_err();
+    assert!(matches!(&err, Error::Status(status) if status.body == body));
+    for shown in [err.to_string(), format!("{:?}", err)] {
+        assert!(shown.len() < 300, "{}", shown);
+        assert!(shown.contains("10000 bytes"), "{}", shown);
+    }
+}
+
+#[test]
+fn error_transport() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}/2.0/", listener.local_addr().unwrap());
+    drop(listener);
+    let err = recent_tracks(endpoint).unwrap_err();
+    assert!(matches!(err, Error::Transport(_)), "{}", err);
+    assert!(err.is_retryable());
+}
+
+#[test]
+fn error_encode() {
+    // Nothing listens here; the request must fail before it is sent.
+    let agent = agent(String::from("http://127.0.0.1:9/2.0/"));
+    let request = tracks::ScrobbleRequest {
+        scrobbles: vec![tracks::Scrobble::default(); 51],
+    };
+    let err = agent.track_scrobble(request, "sk").unwrap_err();
+    assert!(matches!(err, Error::Encode(_)), "{}", err);
+    assert!(!err.is_retryable());
+}
@@ -0,0 +1,101 @@
+#![cfg(feature = "mock")]
+
+use prost_lastfm::error::{Error, ErrorCode, LastFMError};
+use prost_lastfm::mock::Call;
+use prost_lastfm::{AuthService, AuthServiceMock, LastFmService, LastFmServiceMock, auth, user};
+
+/// Stands in for code that only knows the trait.
+fn latest_track<S: LastFmService<Error = Error>>(
+    service: &S,
+    session_token: &str,
+) -> Result<Option<String>, Error> {
+    let response = service.user_get_recent_tracks(
+        user::GetRecentTracksRequest {
+            limit: Some(1),
+            ..Default::default()
+        },
+        Some(session_token),
+    )?;
+    Ok(response
+        .recenttracks
+        .and_then(|recent| recent.track.into_iter().next())
+        .map(|track| track.name))
+}
+
+fn recent_tracks(name: &str) -> user::GetRecentTracksResponse {
+    user::GetRecentTracksResponse {
+        recenttracks: Some(prost_lastfm::RecentTracks {
+            track: vec![prost_lastfm::Track {
+                name: name.to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }),
+    }
+}
+
+#[test]
+fn mock_queued_responses() {
+    let mock = LastFmServiceMock::new();
+    mock.user_get_recent_tracks
+        .push_ok(recent_tracks("First"))
+        .push_ok(recent_tracks("Second"));
+    assert_eq!(
+        latest_track(&mock, "session").unwrap().as_deref(),
+        Some("First")
+    );
+    assert_eq!(
+        latest_track(&mock, "session").unwrap().as_deref(),
+        Some("Second")
+    );
+    mock.user_get_recent_tracks.assert_called(2);
+    mock.user_get_info.assert_not_called();
+    mock.assert_done();
+
+    let calls = mock.user_get_recent_tracks.take_calls();
+    assert_eq!(calls[0].request.limit, Some(1));
+    assert_eq!(calls[0].session_token.as_deref(), Some("session"));
+    assert_eq!(mock.user_get_recent_tracks.call_count(), 0);
+}
+
+#[test]
+fn mock_errors() {
+    let mock = AuthServiceMock::new();
+    mock.auth_get_session.push_err(LastFMError {
+        message: String::from("This token has not been authorized"),
+        error: ErrorCode::TokenUnauthorized,
+        retry_after: None,
+    });
+    let err = mock
+        .auth_get_session(auth::GetSessionRequest {
+            token: String::from("token"),
+        })
+        .unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::TokenUnauthorized));
+    assert_eq!(
+        mock.auth_get_session.calls(),
+        [Call {
+            request: auth::GetSessionRequest {
+                token: String::from("token"),
+            },
+            session_token: None,
+        }]
+    );
+}
+
+#[test]
+#[should_panic(expected = "auth.getToken was called with no response queued")]
+fn mock_without_response() {
+    let mock = AuthServiceMock::new();
+    let _ = mock.auth_get_token(auth::GetTokenRequest {});
+}
+
+#[test]
+#[should_panic(expected = "auth.getToken has 1 responses left")]
+fn mock_unused_response() {
+    let mock = AuthServiceMock::new();
+    mock.auth_get_token.push_ok(auth::GetTokenResponse {
+        token: String::from("token"),
+    });
+    mock.assert_done();
+}
@@ -180,3 +180,241 @@ fn parse_negative() -> Result<(), String> {
     }
     Ok(())
 }
+
+const SCROBBLE_RAW: &[u8] = br##"
+{
+    "scrobbles": {
+        "scrobble": {
+            "artist": {
+                "corrected": "0",
+                "#text": "Boards of Canada"
+            },
+            "album": {
+                "corrected": "0",
+                "#text": "The Campfire Headphase"
+            },
+            "track": {
+                "corrected": "0",
+                "#text": "Into the Rainbow Vein"
+            },
+            "albumArtist": {
+                "corrected": "0",
+                "#text": ""
+            },
+            "ignoredMessage": {
+                "code": "0",
+                "#text": ""
+            },
+            "timestamp": "1732873371"
+        },
+        "@attr": {
+            "ignored": 0,
+            "accepted": 1
+        }
+    }
+}
+"##;
+
+const SCROBBLE_IGNORED_RAW: &[u8] = br##"
+{
+    "scrobbles": {
+        "scrobble": [
+            {
+                "artist": {
+                    "corrected": "0",
+                    "#text": "Boards of Canada"
+                },
+                "album": {
+                    "corrected": "0"
+                },
+                "track": {
+                    "corrected": "0",
+                    "#text": "Into the Rainbow Vein"
+                },
+                "albumArtist": {
+                    "corrected": "0",
+                    "#text": ""
+                },
+                "ignoredMessage": {
+                    "code": "0",
+                    "#text": ""
+                },
+                "timestamp": "1732873371"
+            },
+            {
+                "artist": {
+                    "corrected": "0",
+                    "#text": "Boards of Canada"
+                },
+                "album": {
+                    "corrected": "0"
+                },
+                "track": {
+                    "corrected": "0",
+                    "#text": "Dayvan Cowboy"
+                },
+                "albumArtist": {
+                    "corrected": "0",
+                    "#text": ""
+                },
+                "ignoredMessage": {
+                    "code": "3",
+                    "#text": "Timestamp too old"
+                },
+                "timestamp": "1000000000"
+            }
+        ],
+        "@attr": {
+            "ignored": 1,
+            "accepted": 1
+        }
+    }
+}
+"##;
+
+const UPDATE_NOW_PLAYING_RAW: &[u8] = br##"
+{
+    "nowplaying": {
+        "artist": {
+            "corrected": "0",
+            "#text": "Boards of Canada"
+        },
+        "track": {
+            "corrected": "0",
+            "#text": "Into the Rainbow Vein"
+        },
+        "ignoredMessage": {
+            "code": "0",
+            "#text": ""
+        },
+        "albumArtist": {
+            "corrected": "0",
+            "#text": ""
+        },
+        "album": {
+            "corrected": "0",
+            "#text": "The Campfire Headphase"
+        }
+    }
+}
+"##;
+
+#[test]
+fn parse_scrobble() -> Result<(), String> {
+    use prost_lastfm::tracks;
+
+    let resp = serde_json::from_slice::<tracks::ScrobbleResponse>(SCROBBLE_RAW)
+        .map_err(|err| format!("ScrobbleResponse error: {}", err))?;
+    let scrobbles = resp.scrobbles.ok_or("missing scrobbles")?;
+    assert_eq!(scrobbles.attr.map(|attr| attr.accepted), Some(1));
+    assert_eq!(scrobbles.scrobble.len(), 1);
+
+    let resp = serde_json::from_slice::<tracks::ScrobbleResponse>(SCROBBLE_IGNORED_RAW)
+        .map_err(|err| format!("ScrobbleResponse error: {}", err))?;
+    let scrobbles = resp.scrobbles.ok_or("missing scrobbles")?;
+    assert_eq!(scrobbles.attr.map(|attr| attr.ignored), Some(1));
+    let ignored = scrobbles.scrobble[1]
+        .ignored_message
+        .as_ref()
+        .ok_or("missing ignoredMessage")?;
+    assert_eq!(ignored.code(), tracks::IgnoredCode::TimestampTooOld);
+
+    let resp = serde_json::from_slice::<tracks::UpdateNowPlayingResponse>(UPDATE_NOW_PLAYING_RAW)
+        .map_err(|err| format!("UpdateNowPlayingResponse error: {}", err))?;
+    let nowplaying = resp.nowplaying.ok_or("missing nowplaying")?;
+    let text = |text: Option<tracks::CorrectedText>| text.map(|text| text.text);
+    assert_eq!(
+        text(nowplaying.track).as_deref(),
+        Some("Into the Rainbow Vein")
+    );
+    assert_eq!(
+        text(nowplaying.album).as_deref(),
+        Some("The Campfire Headphase")
+    );
+    let ignored = nowplaying.ignored_message.ok_or("missing ignoredMessage")?;
+    assert_eq!(ignored.code(), tracks::IgnoredCode::None);
+    Ok(())
+}
+
+const TRACK_GET_INFO_RAW: &[u8] = br##"
+{
+    "track": {
+        "name": "Into the Rainbow Vein",
+        "mbid": "4131e57f-4826-4c0d-89aa-5205e0fee918",
+        "url": "https://www.last.fm/music/Boards+of+Canada/_/Into+the+Rainbow+Vein",
+        "duration": "258000",
+        "streamable": {
+            "#text": "0",
+            "fulltrack": "0"
+        },
+        "listeners": "150000",
+        "playcount": "900000",
+        "artist": {
+            "name": "Boards of Canada",
+            "mbid": "69158f97-4c07-4c4e-baf8-4e4ab1ed666e",
+            "url": "https://www.last.fm/music/Boards+of+Canada"
+        },
+        "album": {
+            "artist": "Boards of Canada",
+            "title": "The Campfire Headphase",
+            "mbid": "21f8abb6-d3ce-4047-9b53-c3ccc4783eef",
+            "url": "https://www.last.fm/music/Boards+of+Canada/The+Campfire+Headphase",
+            "image": [
+                {
+                    "#text": "https://lastfm.freetls.fastly.net/i/u/34s/77b2419ede333b1b20ab565305bd8039.png",
+                    "size": "small"
+                },
+                {
+                    "#text": "https://lastfm.freetls.fastly.net/i/u/64s/77b2419ede333b1b20ab565305bd8039.png",
+                    "size": "medium"
+                }
+            ],
+            "@attr": {
+                "position": "1"
+            }
+        },
+        "userplaycount": "12",
+        "userloved": "0",
+        "toptags": {
+            "tag": []
+        }
+    }
+}
+"##;
+
+const TRACK_GET_INFO_SPARSE_RAW: &[u8] = br##"
+{
+    "track": {
+        "name": "Untitled",
+        "url": "https://www.last.fm/music/Someone/_/Untitled",
+        "duration": "0",
+        "listeners": "1",
+        "playcount": "1",
+        "artist": {
+            "name": "Someone",
+            "url": "https://www.last.fm/music/Someone"
+        }
+    }
+}
+"##;
+
+#[test]
+fn parse_track_info() -> Result<(), String> {
+    use prost_lastfm::tracks;
+
+    let resp = serde_json::from_slice::<tracks::GetInfoResponse>(TRACK_GET_INFO_RAW)
+        .map_err(|err| format!("GetInfoResponse error: {}", err))?;
+    let track = resp.track.ok_or("missing track")?;
+    assert_eq!(track.duration, 258000);
+    assert_eq!(track.userplaycount, Some(12));
+    assert_eq!(track.userloved, Some(false));
+    assert_eq!(track.album.map(|album| album.image.len()), Some(2));
+
+    let resp = serde_json::from_slice::<tracks::GetInfoResponse>(TRACK_GET_INFO_SPARSE_RAW)
+        .map_err(|err| format!("GetInfoResponse error: {}", err))?;
+    let track = resp.track.ok_or("missing track")?;
+    assert_eq!(track.duration, 0);
+    assert_eq!(track.userplaycount, None);
+    assert!(track.album.is_none());
+    Ok(())
+}
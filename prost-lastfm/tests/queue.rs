@@ -0,0 +1,223 @@
+// The queue is only built with the blocking service trait.
+#![cfg(all(feature = "mock", feature = "gen_sync"))]
+
+use prost_lastfm::error::{DecodeError, Error, ErrorCode, LastFMError};
+use prost_lastfm::queue::ScrobbleQueue;
+use prost_lastfm::{LastFmServiceMock, tracks};
+use std::path::PathBuf;
+
+fn accepted(accepted: u32, ignored: u32) -> tracks::ScrobbleResponse {
+    tracks::ScrobbleResponse {
+        scrobbles: Some(tracks::Scrobbles {
+            attr: Some(tracks::scrobbles::Attributes { accepted, ignored }),
+            scrobble: Vec::new(),
+        }),
+    }
+}
+
+fn lastfm_error(code: ErrorCode) -> LastFMError {
+    LastFMError {
+        message: String::new(),
+        error: code,
+        retry_after: None,
+    }
+}
+
+/// The size of each batch the mock received.
+fn batches(mock: &LastFmServiceMock) -> Vec<usize> {
+    mock.track_scrobble
+        .calls()
+        .iter()
+        .map(|call| call.request.scrobbles.len())
+        .collect()
+}
+
+fn scrobble(timestamp: u64) -> tracks::Scrobble {
+    tracks::Scrobble {
+        artist: "Boards of Canada".to_string(),
+        track: "Into the Rainbow Vein".to_string(),
+        timestamp,
+        album: Some("The Campfire Headphase".to_string()),
+        duration: Some(253),
+        chosen_by_user: Some(true),
+        ..Default::default()
+    }
+}
+
+/// A queue file in a temporary directory, removed when the guard is dropped.
+fn queue_path() -> (tempfile::TempDir, PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("scrobbles.json");
+    (dir, path)
+}
+
+#[test]
+fn queue_persists() -> Result<(), String> {
+    let (_dir, path) = queue_path();
+    let mut queue = ScrobbleQueue::open(&path).map_err(|err| err.to_string())?;
+    for timestamp in 0..3 {
+        queue
+            .push(scrobble(timestamp))
+            .map_err(|err| err.to_string())?;
+    }
+    let reopened = ScrobbleQueue::open(&path).map_err(|err| err.to_string())?;
+    assert_eq!(
+        reopened.pending().collect::<Vec<_>>(),
+        queue.pending().collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+#[test]
+fn queue_flushes_in_batches() -> Result<(), String> {
+    let (_dir, path) = queue_path();
+    let mut queue = ScrobbleQueue::open(&path).map_err(|err| err.to_string())?;
+    for timestamp in 0..120 {
+        queue
+            .push(scrobble(timestamp))
+            .map_err(|err| err.to_string())?;
+    }
+    let mock = LastFmServiceMock::new();
+    mock.track_scrobble
+        .push_ok(accepted(50, 0))
+        .push_ok(accepted(49, 1))
+        .push_ok(accepted(20, 0));
+    let report = queue.flush(&mock, "sk").map_err(|err| err.to_string())?;
+    assert_eq!(batches(&mock), vec![50, 50, 20]);
+    mock.assert_done();
+    assert_eq!(report.accepted, 119);
+    assert_eq!(report.ignored, 1);
+    assert!(report.error.is_none());
+    assert!(queue.is_empty());
+    Ok(())
+}
+
+#[test]
+fn queue_retries_only_retryable() -> Result<(), String> {
+    let (_dir, path) = queue_path();
+    let mut queue = ScrobbleQueue::open(&path)
+        .map_err(|err| err.to_string())?
+        .with_max_batch(2);
+    for timestamp in 0..6 {
+        queue
+            .push(scrobble(timestamp))
+            .map_err(|err| err.to_string())?;
+    }
+    let mock = LastFmServiceMock::new();
+    mock.track_scrobble
+        .push_ok(accepted(2, 0))
+        .push_err(lastfm_error(ErrorCode::InvalidParameters))
+        .push_ok(accepted(1, 0))
+        .push_err(lastfm_error(ErrorCode::InvalidParameters))
+        .push_err(lastfm_error(ErrorCode::ServiceOffline));
+    let report = queue.flush(&mock, "sk").map_err(|err| err.to_string())?;
+    assert_eq!(batches(&mock), vec![2, 2, 1, 1, 2]);
+    assert_eq!(report.accepted, 3);
+    assert_eq!(report.dropped, 1);
+    assert!(matches!(
+        report.error,
+        Some(Error::LastFM(LastFMError {
+            error: ErrorCode::ServiceOffline,
+            ..
+        }))
+    ));
+    let reopened = ScrobbleQueue::open(&path).map_err(|err| err.to_string())?;
+    assert_eq!(
+        reopened
+            .pending()
+            .map(|scrobble| scrobble.timestamp)
+            .collect::<Vec<_>>(),
+        vec![4, 5]
+    );
+    Ok(())
+}
+
+#[test]
+fn queue_drops_only_rejected_scrobbles() -> Result<(), String> {
+    let (_dir, path) = queue_path();
+    let mut queue = ScrobbleQueue::open(&path)
+        .map_err(|err| err.to_string())?
+        .with_max_batch(4);
+    for timestamp in 0..4 {
+        queue
+            .push(scrobble(timestamp))
+            .map_err(|err| err.to_string())?;
+    }
+    // Only the scrobble at timestamp 2 is rejected.
+    let mock = LastFmServiceMock::new();
+    mock.track_scrobble
+        .push_err(lastfm_error(ErrorCode::InvalidParameters))
+        .push_ok(accepted(2, 0))
+        .push_err(lastfm_error(ErrorCode::InvalidParameters))
+        .push_err(lastfm_error(ErrorCode::InvalidParameters))
+        .push_ok(accepted(1, 0));
+    let report = queue.flush(&mock, "sk").map_err(|err| err.to_string())?;
+    let sent = mock
+        .track_scrobble
+        .take_calls()
+        .into_iter()
+        .map(|call| {
+            call.request
+                .scrobbles
+                .iter()
+                .map(|scrobble| scrobble.timestamp)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        sent,
+        vec![vec![0, 1, 2, 3], vec![0, 1], vec![2, 3], vec![2], vec![3]]
+    );
+    mock.assert_done();
+    assert_eq!(report.accepted, 3);
+    assert_eq!(report.dropped, 1);
+    assert!(report.error.is_none());
+    assert!(queue.is_empty());
+    Ok(())
+}
+
+#[test]
+fn queue_keeps_batch_on_auth_failure() -> Result<(), String> {
+    let (_dir, path) = queue_path();
+    let mut queue = ScrobbleQueue::open(&path).map_err(|err| err.to_string())?;
+    queue.push(scrobble(0)).map_err(|err| err.to_string())?;
+    let mock = LastFmServiceMock::new();
+    mock.track_scrobble
+        .push_err(lastfm_error(ErrorCode::InvalidSessionKey));
+    let report = queue.flush(&mock, "sk").map_err(|err| err.to_string())?;
+    assert_eq!(
+        mock.track_scrobble.calls()[0].session_token.as_deref(),
+        Some("sk")
+    );
+    assert_eq!(report.dropped, 0);
+    assert!(report.error.is_some_and(|err| err.is_auth_failure()));
+    assert_eq!(queue.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn queue_unreadable_response_counts_as_sent() -> Result<(), String> {
+    let (_dir, path) = queue_path();
+    let mut queue = ScrobbleQueue::open(&path)
+        .map_err(|err| err.to_string())?
+        .with_max_batch(2);
+    for timestamp in 0..3 {
+        queue
+            .push(scrobble(timestamp))
+            .map_err(|err| err.to_string())?;
+    }
+    let mock = LastFmServiceMock::new();
+    mock.track_scrobble
+        .push_err(DecodeError {
+            source: serde_json::from_str::<u32>("{}").unwrap_err(),
+            body: String::from("{\"scrobbles\":[]}"),
+        })
+        .push_ok(accepted(1, 0));
+    let report = queue.flush(&mock, "sk").map_err(|err| err.to_string())?;
+    assert_eq!(batches(&mock), vec![2, 1]);
+    assert_eq!(report.unconfirmed, 2);
+    assert_eq!(report.accepted, 1);
+    assert!(report.error.is_none());
+    assert!(queue.is_empty());
+    Ok(())
+}
@@ -0,0 +1,260 @@
+use prost_lastfm::error::Error;
+use prost_lastfm::fixture::{Fixture, REDACTED, Recorder, Replay, check_fixtures};
+use prost_lastfm::transport::{Request, Response, Transport};
+use prost_lastfm::{
+    AuthService, AuthServiceAgent, LastFmService, LastFmServiceAgent, auth, tracks, user,
+};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+const ENDPOINT: &str = "https://ws.audioscrobbler.com/2.0/";
+
+prost_lastfm::fixture_tests! {
+    FIXTURES =>
+    #[ignore = "no fixtures recorded from Last.fm yet, see replay_record"]
+    replay_auth_responses: prost_lastfm::AUTH_SERVICE_RESPONSES,
+    #[ignore = "no fixtures recorded from Last.fm yet, see replay_record"]
+    replay_lastfm_responses: prost_lastfm::LAST_FM_SERVICE_RESPONSES,
+}
+
+fn lastfm_agent<T>(transport: T) -> LastFmServiceAgent<T> {
+    LastFmServiceAgent::new(
+        transport,
+        String::from("key"),
+        String::from("secret"),
+        String::from(ENDPOINT),
+    )
+}
+
+/// Answers every request with the same body.
+struct Canned(&'static str);
+
+impl Transport for Canned {
+    fn send(&self, _request: Request) -> Result<Response, Error> {
+        Ok(Response::ok(self.0))
+    }
+}
+
+const SESSION: &str = r#"{"session":{"name":"someone","key":"1234567890ABCDEF","subscriber":0}}"#;
+
+const RECENT_TRACKS: &str = r##"{"recenttracks":{"track":[{
+    "name":"Into the Rainbow Vein",
+    "artist":{"mbid":"","#text":"Boards of Canada"},
+    "album":{"mbid":"","#text":"The Campfire Headphase"},
+    "url":"https://www.last.fm/music/Boards+of+Canada/_/Into+the+Rainbow+Vein",
+    "image":[],
+    "mbid":"",
+    "streamable":"0"
+}]}}"##;
+
+#[test]
+fn replay_agent() {
+    let dir = tempfile::tempdir().unwrap();
+    let recent_tracks = |limit| user::GetRecentTracksRequest {
+        limit: Some(limit),
+        ..Default::default()
+    };
+    lastfm_agent(Recorder::new(Canned(RECENT_TRACKS), dir.path()))
+        .user_get_recent_tracks(recent_tracks(1), Some("session"))
+        .unwrap();
+
+    // The session key is redacted, so it does not have to match.
+    let agent = lastfm_agent(Replay::open(dir.path()).unwrap());
+    let recent = agent
+        .user_get_recent_tracks(recent_tracks(1), Some("another session"))
+        .unwrap();
+    let track = &recent.recenttracks.unwrap().track[0];
+    assert_eq!(track.name, "Into the Rainbow Vein");
+
+    let err = agent
+        .user_get_recent_tracks(recent_tracks(50), Some("another session"))
+        .unwrap_err();
+    assert!(matches!(err, Error::Transport(_)), "{}", err);
+    assert!(!err.to_string().contains("another session"), "{}", err);
+}
+
+#[test]
+fn replay_recorder() {
+    let dir = tempfile::tempdir().unwrap();
+    let agent = AuthServiceAgent::new(
+        Recorder::new(Canned(SESSION), dir.path()),
+        String::from("key"),
+        String::from("secret"),
+        String::from(ENDPOINT),
+    );
+    let login = || {
+        agent.auth_get_mobile_session(auth::GetMobileSessionRequest {
+            username: String::from("someone"),
+            password: String::from("hunter2"),
+        })
+    };
+    // The caller still gets the real session.
+    assert_eq!(login().unwrap().session.unwrap().key, "1234567890ABCDEF");
+    login().unwrap();
+    agent
+        .auth_get_session(auth::GetSessionRequest {
+            token: String::from("0123456789abcdef"),
+        })
+        .unwrap();
+
+    let fixtures = Fixture::load_dir(dir.path()).unwrap();
+    let names = fixtures
+        .iter()
+        .map(|(path, _)| path.file_name().unwrap().to_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            "auth.getMobileSession-000.json",
+            "auth.getMobileSession-001.json",
+            "auth.getSession-000.json"
+        ]
+    );
+    let fixture = &fixtures[0].1;
+    assert_eq!(fixture.method, "auth.getMobileSession");
+    assert_eq!(fixture.params["username"], "someone");
+    for param in ["api_key", "api_sig", "password"] {
+        assert_eq!(fixture.params[param], REDACTED);
+    }
+    assert_eq!(fixture.body["session"]["key"], REDACTED);
+    assert_eq!(fixtures[2].1.params["token"], REDACTED);
+    for (path, _) in &fixtures {
+        let saved = std::fs::read_to_string(path).unwrap();
+        for secret in ["hunter2", "1234567890ABCDEF", "0123456789abcdef"] {
+            assert!(!saved.contains(secret), "{} in {}", secret, saved);
+        }
+    }
+    // What was recorded decodes as the methods' responses.
+    check_fixtures(dir.path(), prost_lastfm::AUTH_SERVICE_RESPONSES);
+}
+
+/// Records fixtures from Last.fm itself, to add to `tests/fixtures` after a
+/// look over. Run with `LASTFM_API_KEY`, `LASTFM_SECRET` and
+/// `LASTFM_SESSION_KEY` set:
+/// `cargo test -p prost-lastfm --test replay -- --ignored replay_record`
+///
+/// Every method is called, so this sets the most recent track as now playing
+/// and scrobbles it again; use a test account. `auth.getMobileSession` is
+/// only recorded if `LASTFM_USERNAME` and `LASTFM_PASSWORD` are set too.
+#[test]
+#[ignore]
+fn replay_record() {
+    let var = |name| std::env::var(name).unwrap_or_else(|_| panic!("{} is not set", name));
+    let (api_key, secret) = (var("LASTFM_API_KEY"), var("LASTFM_SECRET"));
+    let session_key = var("LASTFM_SESSION_KEY");
+    // Kept for looking over, so not removed.
+    let dir = std::env::temp_dir().join(format!("prost-lastfm-recorded-{}", std::process::id()));
+    let client = reqwest::blocking::Client::new();
+
+    let auth = AuthServiceAgent::new(
+        Recorder::new(client.clone(), &dir),
+        api_key.clone(),
+        secret.clone(),
+        String::from(ENDPOINT),
+    );
+    let token = auth.auth_get_token(auth::GetTokenRequest {}).unwrap().token;
+    // The token is not approved, so this records Last.fm's error.
+    let _ = auth.auth_get_session(auth::GetSessionRequest { token });
+    if let (Ok(username), Ok(password)) = (
+        std::env::var("LASTFM_USERNAME"),
+        std::env::var("LASTFM_PASSWORD"),
+    ) {
+        auth.auth_get_mobile_session(auth::GetMobileSessionRequest { username, password })
+            .unwrap();
+    }
+
+    let agent = LastFmServiceAgent::new(
+        Recorder::new(client, &dir),
+        api_key,
+        secret,
+        String::from(ENDPOINT),
+    );
+    let user = agent
+        .user_get_info(user::GetInfoRequest { user: None }, Some(&session_key))
+        .unwrap()
+        .user
+        .unwrap();
+    let mut recent = None;
+    for (limit, extended) in [(1, None), (20, Some(true))] {
+        let response = agent
+            .user_get_recent_tracks(
+                user::GetRecentTracksRequest {
+                    limit: Some(limit),
+                    extended,
+                    ..Default::default()
+                },
+                Some(&session_key),
+            )
+            .unwrap();
+        // Extended responses name the artist differently.
+        recent.get_or_insert(response.recenttracks.unwrap().track);
+    }
+    agent
+        .user_get_friends(
+            user::GetFriendsRequest {
+                user: Some(user.name.clone()),
+                ..Default::default()
+            },
+            Some(&session_key),
+        )
+        .unwrap();
+
+    let track = recent
+        .into_iter()
+        .flatten()
+        .next()
+        .expect("no recent tracks");
+    let artist = track.artist.unwrap_or_default().text;
+    let album = track
+        .album
+        .map(|album| album.text)
+        .filter(|album| !album.is_empty());
+    for (artist, name) in [
+        (artist.clone(), track.name.clone()),
+        // Records Last.fm's error for a track it does not know.
+        (
+            artist.clone(),
+            String::from("eclect fixture: no such track"),
+        ),
+    ] {
+        let _ = agent.track_get_info(
+            tracks::GetInfoRequest {
+                artist: Some(artist),
+                track: Some(name),
+                username: Some(user.name.clone()),
+                ..Default::default()
+            },
+            Some(&session_key),
+        );
+    }
+    agent
+        .track_update_now_playing(
+            tracks::UpdateNowPlayingRequest {
+                artist: artist.clone(),
+                track: track.name.clone(),
+                album: album.clone(),
+                ..Default::default()
+            },
+            &session_key,
+        )
+        .unwrap();
+    let timestamp = (SystemTime::now() - Duration::from_secs(600))
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    agent
+        .track_scrobble(
+            tracks::ScrobbleRequest {
+                scrobbles: vec![tracks::Scrobble {
+                    artist,
+                    track: track.name,
+                    timestamp,
+                    album,
+                    ..Default::default()
+                }],
+            },
+            &session_key,
+        )
+        .unwrap();
+    println!("Recorded to {}", dir.display());
+}
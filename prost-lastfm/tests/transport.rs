@@ -0,0 +1,115 @@
+use prost_lastfm::error::{Error, TransportError};
+use prost_lastfm::transport::{HttpMethod, Request, Response, Transport};
+use prost_lastfm::{AuthService, AuthServiceAgent, LastFmService, LastFmServiceAgent, auth, user};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// Answers from a list and keeps the requests, without any networking.
+struct Fake {
+    responses: RefCell<Vec<Result<Response, Error>>>,
+    requests: RefCell<Vec<Request>>,
+}
+
+impl Fake {
+    fn new(responses: Vec<Result<Response, Error>>) -> Self {
+        Self {
+            responses: RefCell::new(responses),
+            requests: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl Transport for Fake {
+    fn send(&self, request: Request) -> Result<Response, Error> {
+        self.requests.borrow_mut().push(request);
+        self.responses.borrow_mut().remove(0)
+    }
+}
+
+fn params(pairs: impl Iterator<Item = (String, String)>) -> HashMap<String, String> {
+    pairs.collect()
+}
+
+#[test]
+fn transport_get() {
+    let fake = Fake::new(vec![Ok(Response::ok(
+        r#"{"session":{"name":"someone","key":"session-key","subscriber":0}}"#,
+    ))]);
+    let agent = AuthServiceAgent::new(
+        &fake,
+        String::from("key"),
+        String::from("secret"),
+        String::from("http://fake/2.0/"),
+    );
+    let response = agent
+        .auth_get_session(auth::GetSessionRequest {
+            token: String::from("token"),
+        })
+        .unwrap();
+    assert_eq!(response.session.unwrap().key, "session-key");
+
+    let requests = fake.requests.borrow();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, HttpMethod::Get);
+    assert_eq!(requests[0].body, None);
+    let query = params(requests[0].url.query_pairs().into_owned());
+    assert_eq!(query["method"], "auth.getSession");
+    assert_eq!(query["token"], "token");
+    let prehash = "api_keykeymethodauth.getSessiontokentokensecret";
+    assert_eq!(query["api_sig"], format!("{:x}", md5::compute(prehash)));
+}
+
+#[test]
+fn transport_post() {
+    let fake = Fake::new(vec![Ok(Response::ok(
+        r#"{"session":{"name":"someone","key":"session-key","subscriber":0}}"#,
+    ))]);
+    let agent = AuthServiceAgent::new(
+        &fake,
+        String::from("key"),
+        String::from("secret"),
+        String::from("http://fake/2.0/"),
+    );
+    agent
+        .auth_get_mobile_session(auth::GetMobileSessionRequest {
+            username: String::from("someone"),
+            password: String::from("hunter2"),
+        })
+        .unwrap();
+
+    let requests = fake.requests.borrow();
+    assert_eq!(requests[0].method, HttpMethod::Post);
+    assert_eq!(requests[0].url.query(), None);
+    let body = params(
+        url::form_urlencoded::parse(requests[0].body.as_deref().unwrap().as_bytes()).into_owned(),
+    );
+    assert_eq!(body["method"], "auth.getMobileSession");
+    assert!(body.contains_key("api_sig"));
+}
+
+#[test]
+fn transport_errors() {
+    let fake = Fake::new(vec![
+        Err(TransportError::new("connection reset", true).into()),
+        Ok(Response {
+            status: reqwest::StatusCode::TOO_MANY_REQUESTS,
+            retry_after: Some(std::time::Duration::from_secs(30)),
+            body: b"slow down".to_vec(),
+        }),
+    ]);
+    let agent = LastFmServiceAgent::new(
+        &fake,
+        String::from("key"),
+        String::from("secret"),
+        String::from("http://fake/2.0/"),
+    );
+    let request = || agent.user_get_recent_tracks(user::GetRecentTracksRequest::default(), None);
+
+    let err = request().unwrap_err();
+    assert!(matches!(err, Error::Transport(_)), "{}", err);
+    assert!(err.is_retryable());
+
+    let err = request().unwrap_err();
+    assert!(matches!(err, Error::Status(_)), "{}", err);
+    assert_eq!(err.retry_after(), Some(std::time::Duration::from_secs(30)));
+}
@@ -0,0 +1,123 @@
+use prost_lastfm::error::{Error, ErrorCode};
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// The shortest delay after Last.fm reports the rate limit was hit.
+pub const RATE_LIMIT_DELAY: Duration = Duration::from_secs(60);
+
+/// How to react to a failed Last.fm request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// Something about the request itself. Trying again at the usual interval
+    /// is fine.
+    Request,
+    /// The service or the network is having trouble. Retried with backoff.
+    Transient,
+    /// Too many requests. Retried with backoff, starting at
+    /// [`RATE_LIMIT_DELAY`].
+    RateLimited,
+    /// The session is no longer valid, and the user needs to authorize the
+    /// program again.
+    Reauth,
+    /// The API key or secret is unusable. Retrying will not help.
+    Fatal,
+}
+
+impl Severity {
+    pub fn of(err: &Error) -> Self {
+        if let Some(code) = err.code() {
+            return Severity::of_code(code);
+        }
+        if err.status() == Some(reqwest::StatusCode::TOO_MANY_REQUESTS) {
+            Severity::RateLimited
+        } else if err.is_auth_failure() {
+            Severity::Reauth
+        } else if err.is_retryable() {
+            Severity::Transient
+        } else {
+            Severity::Request
+        }
+    }
+
+    pub fn of_code(code: ErrorCode) -> Self {
+        match code {
+            ErrorCode::InvalidApiKey
+            | ErrorCode::SuspendedApiKey
+            | ErrorCode::InvalidMethodSignature => Severity::Fatal,
+            ErrorCode::InvalidSessionKey | ErrorCode::AuthenticationFailed => Severity::Reauth,
+            ErrorCode::RateLimitExceeded => Severity::RateLimited,
+            ErrorCode::ServiceOffline
+            | ErrorCode::InternalError
+            | ErrorCode::TemporarilyUnavailable
+            | ErrorCode::OperationFailed => Severity::Transient,
+            _ => Severity::Request,
+        }
+    }
+
+    /// Whether the program cannot go on without the user's help.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, Severity::Reauth | Severity::Fatal)
+    }
+}
+
+/// Exponential backoff between retries of a failing request. Each failure in
+/// a row doubles the delay, up to `max`, with up to half the delay again added
+/// at random so that clients do not retry in step.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    base: Duration,
+    max: Duration,
+    failures: u32,
+}
+
+impl Backoff {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self {
+            base,
+            max: max.max(base),
+            failures: 0,
+        }
+    }
+
+    /// Failures with backoff since the last success.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Call after a success, so the next failure starts from the base delay.
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+
+    /// The delay before trying again after `err`, at least as long as the
+    /// server asked for.
+    pub fn error(&mut self, err: &Error) -> Option<Duration> {
+        let delay = self.failure(Severity::of(err))?;
+        Some(err.retry_after().map_or(delay, |after| delay.max(after)))
+    }
+
+    /// The delay before trying again after a failure, or `None` if it should
+    /// not be retried.
+    pub fn failure(&mut self, severity: Severity) -> Option<Duration> {
+        let base = match severity {
+            Severity::Request => return Some(self.base),
+            Severity::Transient => self.base,
+            Severity::RateLimited => self.base.max(RATE_LIMIT_DELAY),
+            Severity::Reauth | Severity::Fatal => return None,
+        };
+        self.failures = self.failures.saturating_add(1);
+        let delay = base
+            .saturating_mul(1 << (self.failures - 1).min(16))
+            .min(self.max);
+        Some((delay + delay.mul_f64(random_fraction() / 2.0)).min(self.max))
+    }
+}
+
+/// A random number in `[0, 1)`. Good enough for jitter without pulling in a
+/// random number generator.
+fn random_fraction() -> f64 {
+    let bits = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
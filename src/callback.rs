@@ -0,0 +1,146 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+/// How long a browser may take to send its request once connected.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often to check for a connection while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long to wait for the callback. Last.fm tokens expire after an hour, so
+/// waiting any longer is no use.
+pub const ACCEPT_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Serves the callback of Last.fm's web authorization on 127.0.0.1. Once the
+/// user approves access, Last.fm sends their browser to the callback URL with
+/// `?token=` added.
+pub struct CallbackListener {
+    listener: TcpListener,
+}
+
+impl CallbackListener {
+    /// Listens on `port`, or on any free port if it is 0.
+    pub fn bind(port: u16) -> std::io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind((Ipv4Addr::LOCALHOST, port))?,
+        })
+    }
+
+    /// The URL to give Last.fm as `cb`.
+    pub fn url(&self) -> std::io::Result<String> {
+        Ok(format!("http://{}/", self.listener.local_addr()?))
+    }
+
+    /// Waits up to `timeout` for a request carrying a token. Other requests,
+    /// such as for a favicon, are answered with an error and skipped. Fails
+    /// with [`std::io::ErrorKind::TimedOut`] once the time is up.
+    pub fn accept(&self, timeout: Duration) -> std::io::Result<Callback> {
+        let deadline = Instant::now() + timeout;
+        // Polled, since a blocking accept cannot time out.
+        self.listener.set_nonblocking(true)?;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    "no authorization callback in time",
+                ));
+            }
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(POLL_INTERVAL.min(remaining));
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            stream.set_nonblocking(false)?;
+            let mut callback = Callback {
+                stream,
+                token: String::new(),
+            };
+            match callback.read_token(READ_TIMEOUT.min(remaining)) {
+                Ok(Some(token)) => {
+                    callback.token = token;
+                    return Ok(callback);
+                }
+                Ok(None) => {
+                    let _ = callback.respond("404 Not Found", "Nothing here.");
+                }
+                Err(err) => println!("Error reading authorization callback: {}", err),
+            }
+        }
+    }
+}
+
+/// A browser waiting to hear whether authorization worked.
+pub struct Callback {
+    stream: TcpStream,
+    pub token: String,
+}
+
+impl Callback {
+    fn read_token(&mut self, timeout: Duration) -> std::io::Result<Option<String>> {
+        self.stream.set_read_timeout(Some(timeout))?;
+        let mut reader = BufReader::new(&self.stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        // The headers are of no use, but are read so the browser does not see
+        // the connection reset.
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 && line != "\r\n" {
+            line.clear();
+        }
+        let mut parts = request_line.split_whitespace();
+        let (Some("GET"), Some(target)) = (parts.next(), parts.next()) else {
+            return Ok(None);
+        };
+        let Ok(url) = url::Url::parse("http://localhost/").and_then(|base| base.join(target))
+        else {
+            return Ok(None);
+        };
+        Ok(url
+            .query_pairs()
+            .find(|(key, value)| key == "token" && !value.is_empty())
+            .map(|(_, value)| value.into_owned()))
+    }
+
+    /// Shows the outcome in the browser.
+    pub fn finish(mut self, result: Result<(), &str>) -> std::io::Result<()> {
+        match result {
+            Ok(()) => self.respond("200 OK", "Eclect is authorized. You can close this page."),
+            Err(err) => self.respond(
+                "500 Internal Server Error",
+                &format!("Authorization failed: {}", err),
+            ),
+        }
+    }
+
+    fn respond(&mut self, status: &str, message: &str) -> std::io::Result<()> {
+        let body = format!(
+            "<!DOCTYPE html>\n<html><head><title>Eclect</title></head><body><p>{}</p></body></html>\n",
+            escape_html(message)
+        );
+        write!(
+            self.stream,
+            "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )?;
+        self.stream.flush()
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -11,6 +11,7 @@ pub enum ConfigError {
     FileParse(toml::de::Error),
     ArgParse(clap::Error),
     Conflict(String),
+    Secret(crate::secret::SecretError),
 }
 
 impl Display for ConfigError {
@@ -20,6 +21,7 @@ impl Display for ConfigError {
             ConfigError::FileParse(err) => <toml::de::Error as Display>::fmt(err, f),
             ConfigError::ArgParse(err) => <clap::Error as Display>::fmt(err, f),
             ConfigError::Conflict(msg) => write!(f, "{}", msg),
+            ConfigError::Secret(err) => <crate::secret::SecretError as Display>::fmt(err, f),
         }
     }
 }
@@ -74,6 +76,9 @@ where
                 ConfigError::Conflict(err) => {
                     (clap::error::ErrorKind::InvalidValue, err)
                 }
+                ConfigError::Secret(err) => {
+                    (clap::error::ErrorKind::InvalidValue, err.to_string())
+                }
             };
             clap::command!().error(ty, err)
         })?;
@@ -88,52 +93,70 @@ where
     }
 }
 
+fn config_arg() -> clap::Arg {
+    clap::Arg::new(CONFIG_FLAG)
+        .long(CONFIG_FLAG)
+        .short(CONFIG_FLAG_SHORT)
+        .exclusive(true)
+        .value_name("FILE")
+        .help("Read flags from a TOML file. Exclusive to other arguments.")
+        .long_help("Read flags from a TOML file. Exclusive to other arguments.")
+}
+
+/// Lets a `Config` be flattened into a parser or used as a subcommand's
+/// arguments, so every subcommand can read the same TOML file.
+impl<T> Args for Config<T>
+where
+    T: clap::Args + serde::de::DeserializeOwned,
+{
+    fn augment_args(cmd: Command) -> Command {
+        T::augment_args(cmd).arg(config_arg())
+    }
+
+    fn augment_args_for_update(cmd: Command) -> Command {
+        T::augment_args_for_update(cmd).arg(config_arg())
+    }
+}
+
 impl<T> CommandFactory for Config<T>
 where
     T: Args,
 {
     fn command() -> Command {
-        let cmd = T::augment_args(clap::command!());
-        cmd.arg(
-            clap::Arg::new(CONFIG_FLAG)
-                .long(CONFIG_FLAG)
-                .short(CONFIG_FLAG_SHORT)
-                .exclusive(true)
-                .value_name("FILE")
-                .help("Read flags from a TOML file. Exclusive to other arguments.")
-                .long_help("Read flags from a TOML file. Exclusive to other arguments."),
-        )
+        T::augment_args(clap::command!()).arg(config_arg())
     }
 
     fn command_for_update() -> Command {
-        T::augment_args_for_update(clap::command!())
+        T::augment_args_for_update(clap::command!()).arg(config_arg())
     }
 }
 
+/// Arrays become one flag per item, so items may contain the delimiter.
 fn value_to_flags(map: &serde_json::Map<String, serde_json::Value>) -> Result<Vec<String>, String> {
-    [Ok(String::from("--"))]
-        .into_iter()
-        .chain(map.iter().map(|(key, val)| {
-            let key_repr = key.replace('_', "-");
-            let val_repr = match val {
-                serde_json::Value::Object(_) => {
-                    return Err(format!("value of \"{}\" is object", key));
-                }
-                serde_json::Value::Array(arr) => {
-                    if arr.iter().any(|v| v.is_object() || v.is_array()) {
-                        return Err(format!("value of \"{}\" is too complex", key));
-                    }
-                    arr.iter()
-                        .map(|v| v.to_string())
-                        .collect::<Vec<_>>()
-                        .join(",")
+    let mut flags = vec![String::from("--")];
+    for (key, val) in map {
+        let key_repr = key.replace('_', "-");
+        let vals = match val {
+            serde_json::Value::Object(_) => {
+                return Err(format!("value of \"{}\" is object", key));
+            }
+            serde_json::Value::Array(arr) => {
+                if arr.iter().any(|v| v.is_object() || v.is_array()) {
+                    return Err(format!("value of \"{}\" is too complex", key));
                 }
+                arr.iter().collect()
+            }
+            other => vec![other],
+        };
+        flags.extend(vals.into_iter().map(|val| {
+            let val_repr = match val {
                 serde_json::Value::String(s) => s.clone(),
                 other => other.to_string(),
             };
-            Ok(format!("--{}={}", key_repr, val_repr))
-        }))
-        .collect()
+            format!("--{}={}", key_repr, val_repr)
+        }));
+    }
+    Ok(flags)
 }
 
 impl<T> clap::Parser for Config<T> where T: clap::Args + serde::de::DeserializeOwned {}
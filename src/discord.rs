@@ -1,53 +1,457 @@
+use crate::backoff::{Backoff, Severity};
+use crate::player::PlayerState;
+use crate::template::{Template, TemplateValues};
 use discord_rich_presence::activity::{Activity, ActivityType};
 use discord_rich_presence::{DiscordIpc, activity};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-pub fn activate(
-    app_id: &str,
-) -> Result<discord_rich_presence::DiscordIpcClient, Box<dyn std::error::Error>> {
-    let mut client = discord_rich_presence::DiscordIpcClient::new(app_id)?;
+/// When the shown track started and will end, in Unix seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PlayTimes {
+    pub start: i64,
+    /// Unset when the track length is unknown, so only the elapsed time shows.
+    pub end: Option<i64>,
+}
+
+/// Tracks when the shown track started.
+#[derive(Default)]
+pub struct PlayClock {
+    last: Option<(PlayerState, PlayTimes)>,
+}
+
+impl PlayClock {
+    pub fn times(&mut self, state: &PlayerState, now: SystemTime) -> PlayTimes {
+        let now = now
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs() as i64)
+            .unwrap_or_default();
+        let length = state
+            .length
+            .map(|length| length.as_secs() as i64)
+            .filter(|length| *length > 0);
+        let start = match (&self.last, state.position) {
+            // A position is only accurate when it was read, which is when the
+            // state last changed.
+            (Some((last, times)), _) if state.continues(last) => times.start,
+            (_, Some(position)) => now - position.as_secs() as i64,
+            // Without a position, the start is when the track first showed up.
+            (Some((last, times)), None)
+                if last.player == state.player && last.track == state.track =>
+            {
+                times.start
+            }
+            _ => now,
+        };
+        // Without a position, a repeat looks the same as the track playing
+        // on, so a track still playing after its length has started over.
+        let start = match length {
+            Some(length) if state.position.is_none() && now >= start + length => {
+                start + length * ((now - start) / length)
+            }
+            _ => start,
+        };
+        let times = PlayTimes {
+            start,
+            end: length.map(|length| start + length),
+        };
+        self.last = Some((state.clone(), times));
+        times
+    }
+}
+
+/// The first delay before connecting to Discord again.
+pub const RETRY_DELAY: Duration = Duration::from_secs(5);
+/// The longest delay before connecting to Discord again.
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(120);
+
+/// A Discord client that may not be connected. Connecting is retried with
+/// backoff until Discord shows up, and again whenever the connection is lost.
+pub struct DiscordConnection<C> {
+    client: C,
+    connected: bool,
+    backoff: Backoff,
+    /// When to try connecting next. Unset to try right away.
+    retry_at: Option<Instant>,
+}
+
+impl<C: DiscordIpc> DiscordConnection<C> {
+    /// Wraps a client that has not connected yet.
+    pub fn new(client: C, backoff: Backoff) -> Self {
+        Self {
+            client,
+            connected: false,
+            backoff,
+            retry_at: None,
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// The client, connected or not.
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.client
+    }
+
+    /// The client, while connected.
+    pub fn client(&mut self) -> Option<&mut C> {
+        self.connected.then_some(&mut self.client)
+    }
+
+    /// How long until the next attempt to connect, or `None` while
+    /// connected.
+    pub fn until_retry(&self, now: Instant) -> Option<Duration> {
+        if self.connected {
+            return None;
+        }
+        Some(self.retry_at.map_or(Duration::ZERO, |retry_at| {
+            retry_at.saturating_duration_since(now)
+        }))
+    }
+
+    /// Connects if disconnected and an attempt is due. Returns whether the
+    /// client just connected, in which case anything shown before is gone.
+    pub fn maintain(&mut self, now: Instant) -> Result<bool, Box<dyn std::error::Error>> {
+        if self.until_retry(now) != Some(Duration::ZERO) {
+            return Ok(false);
+        }
+        match self.client.connect() {
+            Ok(()) => {
+                self.connected = true;
+                self.backoff.reset();
+                self.retry_at = None;
+                Ok(true)
+            }
+            Err(err) => {
+                let delay = self
+                    .backoff
+                    .failure(Severity::Transient)
+                    .unwrap_or(MAX_RETRY_DELAY);
+                self.retry_at = Some(now + delay);
+                Err(err)
+            }
+        }
+    }
+
+    /// Drops a connection that failed. The next attempt is made right away.
+    pub fn disconnect(&mut self) {
+        if self.connected {
+            // The connection is already broken, so closing it may fail too.
+            let _ = self.client.close();
+            self.connected = false;
+        }
+        self.retry_at = None;
+    }
+
+    /// Clears the presence and closes the connection before exiting, so that
+    /// Discord does not keep showing the track.
+    pub fn shutdown(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.connected {
+            return Ok(());
+        }
+        self.connected = false;
+        let cleared = self.client.clear_activity();
+        self.client.close()?;
+        cleared
+    }
+}
+
+/// Connects and disconnects again, to check that Discord is reachable.
+pub fn probe<C: DiscordIpc>(client: &mut C) -> Result<(), Box<dyn std::error::Error>> {
     client.connect()?;
-    Ok(client)
-}
-
-fn build_assets<'a>(text: &'a str, images: &'a Vec<prost_lastfm::Image>) -> activity::Assets<'a> {
-    match images.iter().find(|image| image.size == "medium") {
-        Some(image) => activity::Assets::new()
-            .large_image(&image.text)
-            .large_text(&text),
-        None => activity::Assets::new(),
-    }
-}
-
-pub fn set_track(
-    client: &mut discord_rich_presence::DiscordIpcClient,
-    track: Option<prost_lastfm::Track>,
-) -> Result<Option<String>, Box<dyn std::error::Error>> {
-    let Some(track) = track else {
-        client.clear_activity()?;
-        return Ok(None);
-    };
-    let title = track.name.as_str();
-    let album = track
-        .album
-        .as_ref()
-        .map(|a| a.text.as_str())
-        .unwrap_or("Unknown Album");
-    let artist = track
-        .artist
-        .as_ref()
-        .map(|a| a.text.as_str())
-        .unwrap_or("Unknown Artist");
-    let page = track.url.as_str();
-
-    let hover_text = format!("{} - {}", artist, album);
-
-    client.set_activity(
-        Activity::new()
-            .activity_type(ActivityType::Listening)
-            .details(&title)
-            .assets(build_assets(&hover_text, &track.image))
-            .buttons(vec![activity::Button::new("View on last.fm", page)]),
-    )?;
-
-    Ok(Some(format!("{} - {}", artist, title)))
+    client.close()
+}
+
+/// Discord rejects text fields longer than this.
+const MAX_TEXT: usize = 128;
+/// Discord rejects button labels longer than this.
+const MAX_LABEL: usize = 32;
+/// Discord shows at most this many buttons.
+pub const MAX_BUTTONS: usize = 2;
+
+/// How each field of the presence is filled in. Fields that render empty are
+/// left out, as are buttons with an empty label or URL.
+#[derive(Clone, Debug)]
+pub struct PresenceTemplates {
+    pub details: Template,
+    pub state: Template,
+    pub large_text: Template,
+    /// An asset key or image URL shown over the corner of the album art.
+    pub small_image: Option<String>,
+    pub small_text: Template,
+    /// Labels and URLs.
+    pub buttons: Vec<(Template, Template)>,
+}
+
+impl Default for PresenceTemplates {
+    fn default() -> Self {
+        Self {
+            details: Template::parse("{title}").unwrap(),
+            state: Template::parse("").unwrap(),
+            large_text: Template::parse("{artist}[ - {album}]").unwrap(),
+            small_image: None,
+            small_text: Template::parse("").unwrap(),
+            buttons: vec![(
+                Template::parse("View on last.fm").unwrap(),
+                Template::parse("{url}").unwrap(),
+            )],
+        }
+    }
+}
+
+impl PresenceTemplates {
+    /// Whether any template refers to `field`, so values that take a lookup
+    /// are only fetched when shown.
+    pub fn uses(&self, field: &str) -> bool {
+        [
+            &self.details,
+            &self.state,
+            &self.large_text,
+            &self.small_text,
+        ]
+        .into_iter()
+        .chain(self.buttons.iter().flat_map(|(label, url)| [label, url]))
+        .any(|template| template.uses(field))
+    }
+
+    pub fn render(&self, values: &TemplateValues) -> PresenceText {
+        let text = |template: &Template, max: usize| {
+            Some(truncate(template.render(values), max)).filter(|text| !text.is_empty())
+        };
+        PresenceText {
+            details: text(&self.details, MAX_TEXT),
+            state: text(&self.state, MAX_TEXT),
+            large_text: text(&self.large_text, MAX_TEXT),
+            small_text: text(&self.small_text, MAX_TEXT),
+            buttons: self
+                .buttons
+                .iter()
+                .filter_map(|(label, url)| Some((text(label, MAX_LABEL)?, text(url, usize::MAX)?)))
+                .take(MAX_BUTTONS)
+                .collect(),
+        }
+    }
+}
+
+/// The rendered text of a presence.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PresenceText {
+    pub details: Option<String>,
+    pub state: Option<String>,
+    pub large_text: Option<String>,
+    pub small_text: Option<String>,
+    pub buttons: Vec<(String, String)>,
+}
+
+fn truncate(mut text: String, max: usize) -> String {
+    if let Some((end, _)) = text.char_indices().nth(max) {
+        text.truncate(end);
+    }
+    text
+}
+
+/// The template values for `track`. `user` is the Last.fm user and
+/// `playcount` their plays of the track, when known.
+pub fn track_values(
+    track: &prost_lastfm::Track,
+    user: Option<&str>,
+    playcount: Option<u64>,
+) -> TemplateValues {
+    let fields = [
+        ("title", Some(track.name.clone())),
+        ("artist", track.artist.as_ref().map(|a| a.text.clone())),
+        ("album", track.album.as_ref().map(|a| a.text.clone())),
+        ("playcount", playcount.map(|count| count.to_string())),
+        ("user", user.map(str::to_string)),
+        ("url", Some(track.url.clone())),
+    ];
+    fields
+        .into_iter()
+        .filter_map(|(field, value)| Some((field, value.filter(|value| !value.is_empty())?)))
+        .collect()
+}
+
+fn medium_image(images: &[prost_lastfm::Image]) -> Option<&str> {
+    images
+        .iter()
+        .find(|image| image.size == "medium")
+        .map(|image| image.text.as_str())
+}
+
+fn build_assets<'a>(
+    text: &'a PresenceText,
+    large_image: Option<&'a str>,
+    small_image: Option<&'a str>,
+) -> activity::Assets<'a> {
+    let mut assets = activity::Assets::new();
+    if let Some(large_image) = large_image {
+        assets = assets.large_image(large_image);
+        if let Some(large_text) = &text.large_text {
+            assets = assets.large_text(large_text);
+        }
+    }
+    if let Some(small_image) = small_image {
+        assets = assets.small_image(small_image);
+        if let Some(small_text) = &text.small_text {
+            assets = assets.small_text(small_text);
+        }
+    }
+    assets
+}
+
+fn build_timestamps(times: PlayTimes) -> activity::Timestamps {
+    let timestamps = activity::Timestamps::new().start(times.start);
+    match times.end {
+        Some(end) => timestamps.end(end),
+        None => timestamps,
+    }
+}
+
+/// Track details the presence was last set with. Any difference means the
+/// presence needs to be set again.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Shown {
+    artist: Option<String>,
+    title: String,
+    album: Option<String>,
+    image: Option<String>,
+    text: PresenceText,
+    times: Option<PlayTimes>,
+}
+
+impl Shown {
+    fn new(track: &prost_lastfm::Track, text: PresenceText, times: Option<PlayTimes>) -> Self {
+        Self {
+            artist: track.artist.as_ref().map(|artist| artist.text.clone()),
+            title: track.name.clone(),
+            album: track.album.as_ref().map(|album| album.text.clone()),
+            image: medium_image(&track.image).map(str::to_string),
+            text,
+            times,
+        }
+    }
+}
+
+/// What the presence was last set to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Pushed {
+    /// Nothing was set yet, or the client reconnected and lost it.
+    Unknown,
+    Cleared,
+    Playing(Box<Shown>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PresenceUpdate {
+    /// The presence already matched, so nothing was sent.
+    Unchanged,
+    /// The presence was set, to the track described.
+    Set(String),
+    Cleared,
+}
+
+/// Sets the presence only when what it shows changes. An unchanged presence
+/// is still sent again every `reassert` in case Discord dropped it.
+pub struct Presence {
+    templates: PresenceTemplates,
+    reassert: Duration,
+    pushed: Pushed,
+    pushed_at: Option<Instant>,
+}
+
+impl Presence {
+    pub fn new(templates: PresenceTemplates, reassert: Duration) -> Self {
+        Self {
+            templates,
+            reassert,
+            pushed: Pushed::Unknown,
+            pushed_at: None,
+        }
+    }
+
+    pub fn templates(&self) -> &PresenceTemplates {
+        &self.templates
+    }
+
+    /// Forgets what was shown, so the next update is sent. Call after the
+    /// client reconnects.
+    pub fn invalidate(&mut self) {
+        self.pushed = Pushed::Unknown;
+        self.pushed_at = None;
+    }
+
+    /// Shows `track`, or clears the presence if there is none, unless that is
+    /// already shown. On error the presence is left unknown so the next
+    /// update is sent.
+    pub fn update<C: DiscordIpc>(
+        &mut self,
+        client: &mut C,
+        track: Option<&prost_lastfm::Track>,
+        values: &TemplateValues,
+        times: Option<PlayTimes>,
+        now: Instant,
+    ) -> Result<PresenceUpdate, Box<dyn std::error::Error>> {
+        let next = match track {
+            Some(track) => Pushed::Playing(Box::new(Shown::new(
+                track,
+                self.templates.render(values),
+                times,
+            ))),
+            None => Pushed::Cleared,
+        };
+        let due = self
+            .pushed_at
+            .is_none_or(|pushed_at| now.duration_since(pushed_at) >= self.reassert);
+        if next == self.pushed && !due {
+            return Ok(PresenceUpdate::Unchanged);
+        }
+        self.invalidate();
+        let update = match &next {
+            Pushed::Playing(shown) => {
+                set_activity(client, shown, self.templates.small_image.as_deref())?;
+                PresenceUpdate::Set(format!(
+                    "{} - {}",
+                    shown.artist.as_deref().unwrap_or("Unknown Artist"),
+                    shown.title
+                ))
+            }
+            _ => {
+                client.clear_activity()?;
+                PresenceUpdate::Cleared
+            }
+        };
+        self.pushed = next;
+        self.pushed_at = Some(now);
+        Ok(update)
+    }
+}
+
+fn set_activity<C: DiscordIpc>(
+    client: &mut C,
+    shown: &Shown,
+    small_image: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let text = &shown.text;
+    let mut activity = Activity::new()
+        .activity_type(ActivityType::Listening)
+        .assets(build_assets(text, shown.image.as_deref(), small_image));
+    if let Some(details) = &text.details {
+        activity = activity.details(details);
+    }
+    if let Some(state) = &text.state {
+        activity = activity.state(state);
+    }
+    if !text.buttons.is_empty() {
+        activity = activity.buttons(
+            text.buttons
+                .iter()
+                .map(|(label, url)| activity::Button::new(label, url))
+                .collect(),
+        );
+    }
+    if let Some(times) = shown.times {
+        activity = activity.timestamps(build_timestamps(times));
+    }
+    client.set_activity(activity)
 }
@@ -1,10 +1,15 @@
-use base64::Engine;
-use prost_lastfm::error::LastFMError;
+use crate::backoff::Backoff;
+use crate::player::{PlaybackStatus, PlayerState};
+use crate::source::{SourceError, TrackSource};
+use crate::token::TokenStore;
 use prost_lastfm::{AuthService, LastFmService, LastFmServiceAgent, track, user};
+use std::time::Duration;
 use url::Url;
 
 pub const PROD_ENDPOINT: &str = "https://ws.audioscrobbler.com/2.0/";
 pub const AUTH_ENDPOINT: &str = "https://last.fm/api/auth/";
+/// Where users revoke sessions. The API has no way to end one.
+pub const APPLICATIONS_URL: &str = "https://www.last.fm/settings/applications";
 
 #[derive(into_enum::IntoEnum)]
 pub enum InitError {
@@ -15,17 +20,8 @@ pub enum InitError {
     #[into_enum(skip)]
     NeedAuth(String),
     IoError(std::io::Error),
-    ReqwestError(reqwest::Error),
-    LastFMError(LastFMError),
-}
-
-impl From<prost_lastfm::error::Error> for InitError {
-    fn from(err: prost_lastfm::error::Error) -> Self {
-        match err {
-            prost_lastfm::error::Error::LastFM(err) => InitError::LastFMError(err),
-            prost_lastfm::error::Error::Reqwest(err) => InitError::ReqwestError(err),
-        }
-    }
+    RequestError(prost_lastfm::error::Error),
+    TokenError(crate::token::TokenError),
 }
 
 #[derive(serde::Deserialize, serde::Serialize, Debug)]
@@ -34,45 +30,38 @@ pub enum InitToken {
     Session(String),
 }
 
-pub fn read_token(token_path: &std::path::Path) -> Result<InitToken, InitError> {
-    let raw_token = std::fs::read_to_string(&token_path)?;
-    let token_data = base64::engine::general_purpose::STANDARD
-        .decode(raw_token)
-        .map_err(|err| {
-            InitError::BadStateFile(format!(
-                "error decoding state file {}: {}",
-                token_path.to_string_lossy(),
-                err
-            ))
-        })?;
-    let token = serde_json::from_slice::<InitToken>(&token_data).map_err(|err| {
-        InitError::BadStateFile(format!(
-            "error parsing contents of {}: {}",
-            token_path.to_string_lossy(),
-            err
-        ))
-    })?;
-    Ok(token)
-}
-
-pub fn write_token(token_path: &std::path::Path, init_token: &InitToken) -> Result<(), InitError> {
-    let json = serde_json::to_string(init_token)
-        .map_err(|err| InitError::Internal(format!("failed to serialize token: {}", err)))?;
-    let encoded = base64::engine::general_purpose::STANDARD.encode(json);
-    std::fs::write(token_path, encoded).map_err(|err| InitError::from(err))?;
-    Ok(())
-}
-
 fn generate_auth_request(
     agent: &prost_lastfm::AuthServiceAgent,
-    token_path: &std::path::Path,
+    tokens: &TokenStore,
 ) -> Result<String, InitError> {
     let request = prost_lastfm::auth::GetTokenRequest {};
     let auth_token = agent.auth_get_token(request)?.token;
-    write_token(token_path, &InitToken::Auth(auth_token.clone()))?;
+    tokens.write(&InitToken::Auth(auth_token.clone()))?;
     Ok(auth_token)
 }
 
+/// Drops the stored session and starts authorization over, for when
+/// Last.fm no longer accepts the session. Returns the error to report, which
+/// carries the new auth token if one was made.
+pub fn restart_auth(
+    client: reqwest::blocking::Client,
+    endpoint: &str,
+    api_key: &str,
+    secret: &str,
+    tokens: &TokenStore,
+) -> InitError {
+    let auth_agent = prost_lastfm::AuthServiceAgent::new(
+        client,
+        api_key.to_string(),
+        secret.to_string(),
+        endpoint.to_string(),
+    );
+    match generate_auth_request(&auth_agent, tokens) {
+        Ok(auth_token) => InitError::NeedAuth(auth_token),
+        Err(err) => err,
+    }
+}
+
 pub fn build_auth_request(api_key: &str, auth_token: &str) -> Url {
     let mut base = Url::parse(AUTH_ENDPOINT).unwrap();
     base.query_pairs_mut()
@@ -81,65 +70,147 @@ pub fn build_auth_request(api_key: &str, auth_token: &str) -> Url {
     base
 }
 
+/// The URL for web authorization, which sends the browser on to `callback`
+/// with a token once the user approves access.
+pub fn build_web_auth_request(api_key: &str, callback: &str) -> Url {
+    let mut base = Url::parse(AUTH_ENDPOINT).unwrap();
+    base.query_pairs_mut()
+        .append_pair("api_key", api_key)
+        .append_pair("cb", callback);
+    base
+}
+
 pub fn eprint_auth_request(api_key: &str, auth_token: &str) {
     let request = build_auth_request(api_key, auth_token);
     eprintln!(
-        "Navigate to {} to authorize this application, then reopen the program.",
+        "Navigate to {} to authorize this application, then reopen the program. \
+        The auth command waits for approval instead.",
         request.as_str()
     )
 }
 
+fn save_session(
+    response: prost_lastfm::auth::GetSessionResponse,
+    tokens: &TokenStore,
+) -> Result<String, InitError> {
+    let Some(session) = response.session else {
+        return Err(InitError::Internal(String::from(
+            "session request error: empty response",
+        )));
+    };
+    tokens.write(&InitToken::Session(session.key.clone()))?;
+    Ok(session.key)
+}
+
+/// Exchanges an approved auth token for a session, and saves it.
+pub fn exchange_token(
+    agent: &prost_lastfm::AuthServiceAgent,
+    tokens: &TokenStore,
+    auth_token: &str,
+) -> Result<String, InitError> {
+    let request = prost_lastfm::auth::GetSessionRequest {
+        token: auth_token.to_string(),
+    };
+    save_session(agent.auth_get_session(request)?, tokens)
+}
+
+/// Logs in with the user's name and password, for computers without a
+/// browser, and saves the session.
+pub fn mobile_session(
+    agent: &prost_lastfm::AuthServiceAgent,
+    tokens: &TokenStore,
+    username: &str,
+    password: &str,
+) -> Result<String, InitError> {
+    let request = prost_lastfm::auth::GetMobileSessionRequest {
+        username: username.to_string(),
+        password: password.to_string(),
+    };
+    save_session(agent.auth_get_mobile_session(request)?, tokens)
+}
+
+/// How often to ask Last.fm whether the user approved access.
+pub const AUTH_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Polls `auth.getSession` every `interval` until the user approves
+/// `auth_token`, then saves and returns the session key. `prompt` is called
+/// with the token to send the user to, and again whenever the token expires
+/// and is replaced.
+pub fn wait_for_session(
+    agent: &prost_lastfm::AuthServiceAgent,
+    tokens: &TokenStore,
+    mut auth_token: String,
+    interval: Duration,
+    mut prompt: impl FnMut(&str),
+) -> Result<String, InitError> {
+    prompt(&auth_token);
+    loop {
+        std::thread::sleep(interval);
+        let request = prost_lastfm::auth::GetSessionRequest {
+            token: auth_token.clone(),
+        };
+        let err = match agent.auth_get_session(request) {
+            Ok(response) => return save_session(response, tokens),
+            Err(err) => err,
+        };
+        match err.code() {
+            // Not approved yet.
+            Some(prost_lastfm::error::ErrorCode::TokenUnauthorized) => {}
+            Some(
+                prost_lastfm::error::ErrorCode::TokenExpired
+                | prost_lastfm::error::ErrorCode::AuthenticationFailed,
+            ) => {
+                auth_token = generate_auth_request(agent, tokens)?;
+                prompt(&auth_token);
+            }
+            _ if err.is_retryable() => println!("Error checking authorization: {}", err),
+            _ => return Err(err.into()),
+        }
+    }
+}
+
 pub fn activate_session(
     client: reqwest::blocking::Client,
+    endpoint: &str,
     api_key: &str,
     secret: &str,
-    token_path: &std::path::Path,
-) -> Result<(LastFmServiceAgent, String), InitError> {
+    tokens: &TokenStore,
+) -> Result<(LastFmServiceAgent, String, prost_lastfm::User), InitError> {
     let auth_agent = prost_lastfm::AuthServiceAgent::new(
         client.clone(),
         api_key.to_string(),
         secret.to_string(),
-        PROD_ENDPOINT.to_string(),
+        endpoint.to_string(),
     );
-    if !token_path.exists() {
-        let auth_token = generate_auth_request(&auth_agent, token_path)?;
+    if !tokens.exists() {
+        let auth_token = generate_auth_request(&auth_agent, tokens)?;
         return Err(InitError::NeedAuth(auth_token));
     }
-    if token_path.exists() && !token_path.is_file() {
+    if !tokens.path().is_file() {
         return Err(InitError::BadStateFile(format!(
             "error accessing {}: not a file",
-            token_path.to_string_lossy()
+            tokens.path().to_string_lossy()
         )));
     }
-    let session_token = match read_token(&token_path)? {
+    let session_token = match tokens.read()? {
         InitToken::Session(session_token) => session_token,
         InitToken::Auth(auth_token) => {
             let request = prost_lastfm::auth::GetSessionRequest {
                 token: auth_token.clone(),
             };
             match auth_agent.auth_get_session(request) {
-                Err(prost_lastfm::error::Error::Reqwest(err)) => {
-                    return Err(InitError::ReqwestError(err));
-                }
                 Err(prost_lastfm::error::Error::LastFM(err)) => {
                     println!("Get Session error: {:?}", err);
                     let auth_token = match err.error {
                         prost_lastfm::error::ErrorCode::AuthenticationFailed => {
-                            generate_auth_request(&auth_agent, token_path)?
+                            generate_auth_request(&auth_agent, tokens)?
                         }
                         _ => auth_token,
                     };
                     return Err(InitError::NeedAuth(auth_token));
                 }
-                Ok(session) => {
-                    let Some(session) = session.session else {
-                        return Err(InitError::Internal(String::from(
-                            "session request error: empty response",
-                        )));
-                    };
-                    write_token(token_path, &InitToken::Session(session.key.clone()))?;
-                    session.key
-                }
+                Err(err) => return Err(err.into()),
+                Ok(response) => save_session(response, tokens)?,
             }
         }
     };
@@ -147,7 +218,7 @@ pub fn activate_session(
         client.clone(),
         api_key.to_string(),
         secret.to_string(),
-        PROD_ENDPOINT.to_string(),
+        endpoint.to_string(),
     );
     let user = lastfm_user_agent
         .user_get_info(user::GetInfoRequest { user: None }, Some(&session_token))?;
@@ -157,7 +228,7 @@ pub fn activate_session(
         )));
     };
     println!("Logged in as {} (url={})", user.name, user.url);
-    Ok((lastfm_user_agent, session_token))
+    Ok((lastfm_user_agent, session_token, user))
 }
 
 pub fn now_playing(
@@ -186,3 +257,177 @@ pub fn now_playing(
     });
     Ok(track)
 }
+
+/// The artist and title of `track`, which identify it to `track.getInfo`.
+fn track_key(track: &prost_lastfm::Track) -> (String, String) {
+    (
+        track
+            .artist
+            .as_ref()
+            .map(|artist| artist.text.clone())
+            .unwrap_or_default(),
+        track.name.clone(),
+    )
+}
+
+/// Looks up a track with `track.getInfo`. The response includes `username`'s
+/// play count of the track when given.
+pub fn track_info(
+    agent: &LastFmServiceAgent,
+    session_token: Option<&str>,
+    track: &prost_lastfm::Track,
+    username: Option<&str>,
+) -> Result<Option<prost_lastfm::tracks::TrackInfo>, prost_lastfm::error::Error> {
+    let (artist, title) = track_key(track);
+    let response = agent.track_get_info(
+        prost_lastfm::tracks::GetInfoRequest {
+            artist: Some(artist),
+            track: Some(title),
+            username: username.map(str::to_string),
+            ..Default::default()
+        },
+        session_token,
+    )?;
+    Ok(response.track)
+}
+
+/// Looks up the length of a track with `track.getInfo`. Last.fm reports 0
+/// for tracks it has no length for.
+pub fn track_length(
+    agent: &LastFmServiceAgent,
+    session_token: Option<&str>,
+    track: &prost_lastfm::Track,
+) -> Result<Option<Duration>, prost_lastfm::error::Error> {
+    Ok(track_info(agent, session_token, track, None)?
+        .map(|track| track.duration)
+        .filter(|duration| *duration > 0)
+        .map(Duration::from_millis))
+}
+
+/// Looks up how many times a user has played tracks, once per track. Lookup
+/// failures are retried on the next call.
+pub struct PlaycountLookup {
+    agent: LastFmServiceAgent,
+    session_token: String,
+    username: String,
+    last: Option<((String, String), Option<u64>)>,
+}
+
+impl PlaycountLookup {
+    pub fn new(agent: LastFmServiceAgent, session_token: String, username: String) -> Self {
+        Self {
+            agent,
+            session_token,
+            username,
+            last: None,
+        }
+    }
+
+    pub fn playcount(&mut self, track: &prost_lastfm::Track) -> Option<u64> {
+        let key = track_key(track);
+        if let Some((_, playcount)) = self.last.as_ref().filter(|(cached, _)| *cached == key) {
+            return *playcount;
+        }
+        match track_info(
+            &self.agent,
+            Some(&self.session_token),
+            track,
+            Some(&self.username),
+        ) {
+            Ok(info) => {
+                let playcount = info.and_then(|info| info.userplaycount);
+                self.last = Some((key, playcount));
+                playcount
+            }
+            Err(err) => {
+                println!("Error looking up play count: {}", err);
+                None
+            }
+        }
+    }
+}
+
+/// Polls the user's now playing. Last.fm only knows a track is playing, not
+/// its position, and only learns of it once another program reports it.
+///
+/// Polling slows down with `backoff` while Last.fm is failing.
+pub struct LastFmSource {
+    agent: LastFmServiceAgent,
+    session_token: String,
+    backoff: Backoff,
+    /// How long to wait before the next poll, if longer than usual.
+    delay: Option<Duration>,
+    /// The length of the last track looked up, by artist and title.
+    length: Option<((String, String), Option<Duration>)>,
+}
+
+impl LastFmSource {
+    pub fn new(agent: LastFmServiceAgent, session_token: String, backoff: Backoff) -> Self {
+        Self {
+            agent,
+            session_token,
+            backoff,
+            delay: None,
+            length: None,
+        }
+    }
+
+    /// The length of `track`, looked up once per track. Lookup failures are
+    /// retried on the next poll.
+    fn length(&mut self, track: &prost_lastfm::Track) -> Option<Duration> {
+        let key = track_key(track);
+        if let Some((_, length)) = self.length.as_ref().filter(|(cached, _)| *cached == key) {
+            return *length;
+        }
+        match track_length(&self.agent, Some(&self.session_token), track) {
+            Ok(length) => {
+                self.length = Some((key, length));
+                length
+            }
+            Err(err) => {
+                println!("Error looking up track length: {}", err);
+                None
+            }
+        }
+    }
+}
+
+impl TrackSource for LastFmSource {
+    fn name(&self) -> String {
+        String::from("last.fm")
+    }
+
+    fn current(&mut self) -> Result<Option<PlayerState>, SourceError> {
+        let track = match now_playing(&self.agent, Some(&self.session_token)) {
+            Ok(track) => {
+                self.backoff.reset();
+                track
+            }
+            Err(err) => {
+                // Errors that are not retried are left for the receiver to
+                // act on, so polling goes on at the usual interval.
+                self.delay = self.backoff.error(&err);
+                return Err(err.into());
+            }
+        };
+        let Some(track) = track else {
+            return Ok(None);
+        };
+        Ok(Some(PlayerState {
+            player: self.name(),
+            status: PlaybackStatus::Playing,
+            length: self.length(&track),
+            track,
+            position: None,
+        }))
+    }
+
+    fn wait(&mut self, timeout: Duration) -> Result<(), SourceError> {
+        std::thread::sleep(
+            self.delay
+                .take()
+                .map_or(timeout, |delay| delay.max(timeout)),
+        );
+        Ok(())
+    }
+}
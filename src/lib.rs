@@ -0,0 +1,13 @@
+pub mod backoff;
+pub mod callback;
+pub mod config;
+pub mod discord;
+pub mod lastfm;
+pub mod mpd;
+pub mod player;
+pub mod secret;
+pub mod source;
+pub mod template;
+pub mod token;
+#[cfg(feature = "mpris")]
+pub mod mpris;
@@ -1,46 +1,159 @@
-mod config;
-mod discord;
-mod lastfm;
-
 use clap::{CommandFactory, Parser};
-use config::Config;
-use discord_rich_presence::DiscordIpc;
+use eclect::backoff::{Backoff, Severity};
+use eclect::config::Config;
+use eclect::player::PlaybackStatus;
+use eclect::secret::{Secret, SecretSource};
+use eclect::source::{SourceError, SourceEvent, Sources, TrackSource};
+use eclect::template::Template;
+use eclect::token::{PassphraseSource, TokenError, TokenStore};
+use eclect::{config, discord, lastfm};
 use http::header::USER_AGENT;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+enum Source {
+    /// Poll Last.fm for the user's now playing.
+    Lastfm,
+    /// Watch local players on the session D-Bus.
+    #[cfg(feature = "mpris")]
+    Mpris,
+    /// Follow an MPD server.
+    Mpd,
+}
+
+#[derive(clap::Parser, Debug)]
+#[clap(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    /// Flags for run, which is the default command.
+    #[clap(flatten)]
+    run: Config<ArgumentConfig>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Show what is playing on Discord. The default.
+    Run(Config<ArgumentConfig>),
+    /// Authorize this application with Last.fm and wait for approval.
+    Auth(Config<ArgumentConfig>),
+    /// Forget the Last.fm session.
+    Logout(Config<ArgumentConfig>),
+    /// Report the Last.fm session and whether Discord is reachable.
+    Status(Config<ArgumentConfig>),
+    /// Show the Last.fm user of the session.
+    Whoami(Config<ArgumentConfig>),
+}
 
 #[derive(clap::Args, serde::Deserialize, Debug)]
-#[clap(author, version, about, long_about = None)]
 struct ArgumentConfig {
-    /// Persistent storage location (Last.fm session token)
+    /// Persistent storage location (Last.fm session token, queued scrobbles)
     #[clap(short, long, default_value_t = workdir_default())]
     workdir: String,
     /// Seconds between Last.fm queries for now playing.
     #[clap(short, long, default_value_t = 15)]
     query_interval: u64,
-    /// The Discord app ID to use.
+    /// Longest wait in seconds between Last.fm queries while it is failing.
+    #[clap(long, default_value_t = 900)]
+    max_backoff: u64,
+    /// Where to read now playing from. With several sources, the first one playing is shown,
+    /// falling back to later ones when earlier ones are idle or unavailable.
+    #[clap(short, long, value_enum, value_delimiter = ',', default_value = "lastfm")]
+    source: Vec<Source>,
+    /// MPRIS players to follow, by bus name without the org.mpris.MediaPlayer2. prefix.
+    /// Follows every player if empty.
+    #[cfg(feature = "mpris")]
+    #[clap(long, value_delimiter = ',')]
+    mpris_allow: Vec<String>,
+    /// MPRIS players to ignore, by bus name without the org.mpris.MediaPlayer2. prefix.
+    #[cfg(feature = "mpris")]
+    #[clap(long, value_delimiter = ',')]
+    mpris_deny: Vec<String>,
+    /// The MPD server to follow: host, host:port, or the path to its socket.
+    #[clap(long, default_value = "localhost:6600")]
+    mpd_address: String,
+    /// The MPD password, if the server requires one: the password itself, env:VAR, file:PATH or
+    /// cmd:COMMAND.
+    #[clap(long)]
+    mpd_password: Option<SecretSource>,
+    /// A file containing the MPD password.
+    #[clap(long)]
+    mpd_password_file: Option<String>,
+    /// Seconds between resending an unchanged presence, in case Discord dropped it.
+    #[clap(long, default_value_t = 300)]
+    reassert_interval: u64,
+    /// Template for the first line of the presence. Fields: {title}, {artist}, {album},
+    /// {playcount}, {user} and {url}. Text in [brackets] is left out unless every field in it is
+    /// known. Double a bracket or brace to show it.
+    #[clap(long, default_value = "{title}")]
+    details: String,
+    /// Template for the second line of the presence. Left out if empty.
+    #[clap(long, default_value = "")]
+    state: String,
+    /// Template for the album art's hover text.
+    #[clap(long, default_value = "{artist}[ - {album}]")]
+    large_text: String,
+    /// An asset key or image URL to show over the corner of the album art.
+    #[clap(long)]
+    small_image: Option<String>,
+    /// Template for the small image's hover text.
+    #[clap(long, default_value = "")]
+    small_text: String,
+    /// Template for a button label. Given once per button, up to 2.
+    /// Buttons with an empty label or URL are left out.
+    #[clap(long, default_value = "View on last.fm")]
+    button_label: Vec<String>,
+    /// Template for a button URL. Given once per button, in the same order as --button-label.
+    #[clap(long, default_value = "{url}")]
+    button_url: Vec<String>,
+    /// The Discord app ID to use: the ID itself, env:VAR, file:PATH or cmd:COMMAND.
     /// Required unless --discord-app-id-file is specified.
     #[clap(long)]
-    discord_app_id: Option<String>,
+    discord_app_id: Option<SecretSource>,
     /// A file containing the Discord app ID to use.
     /// Required unless --discord-app-id is specified.
     #[clap(long)]
     discord_app_id_file: Option<String>,
-    /// The Last.fm API key to use.
-    /// Required unless --lastfm--api-key-file is specified.
+    /// The Last.fm API key to use: the key itself, env:VAR, file:PATH or cmd:COMMAND.
+    /// Required unless --lastfm-api-key-file is specified.
     #[clap(long)]
-    lastfm_api_key: Option<String>,
+    lastfm_api_key: Option<SecretSource>,
     /// A file containing the Last.fm API key to use.
-    /// Required unless --lastfm--api-key is specified.
+    /// Required unless --lastfm-api-key is specified.
     #[clap(long)]
     lastfm_api_key_file: Option<String>,
-    /// The Last.fm API secret to use.
+    /// The Last.fm API secret to use: the secret itself, env:VAR, file:PATH or cmd:COMMAND.
     /// Required unless --lastfm-secret-file is specified.
     #[clap(long)]
-    lastfm_secret: Option<String>,
+    lastfm_secret: Option<SecretSource>,
     /// A file containing the Last.fm API secret to use.
     /// Required unless --lastfm-secret is specified.
     #[clap(long)]
     lastfm_secret_file: Option<String>,
+    /// The Last.fm API to use, such as a local server for testing.
+    #[clap(long, default_value = lastfm::PROD_ENDPOINT)]
+    endpoint: String,
+    /// Open the Last.fm authorization page in the default browser when authorization is needed.
+    #[clap(long, num_args = 0..=1, default_missing_value = "true", default_value_t = false)]
+    open_browser: bool,
+    /// Authorize through Last.fm's web flow instead, listening on 127.0.0.1 at this port for the
+    /// browser to return. 0 picks a free port.
+    #[clap(long)]
+    web_auth_port: Option<u16>,
+    /// The Last.fm user to log in as with --password-file, instead of authorizing in a browser.
+    #[clap(long, requires = "password_file")]
+    username: Option<String>,
+    /// A file containing the Last.fm password of --username.
+    #[clap(long, requires = "username")]
+    password_file: Option<String>,
+    /// Encrypt the stored Last.fm session with the passphrase in this file.
+    #[clap(long, conflicts_with = "token_passphrase_command")]
+    token_passphrase_file: Option<String>,
+    /// Encrypt the stored Last.fm session with the passphrase this command prints, such as from a
+    /// password manager.
+    #[clap(long)]
+    token_passphrase_command: Option<String>,
 }
 
 fn workdir_default() -> String {
@@ -51,59 +164,189 @@ fn workdir_default() -> String {
         .into_owned()
 }
 
-fn file_or_string(path: Option<String>, path_name: &str, arg: Option<String>, arg_name: &str) -> Result<String, config::ConfigError> {
-    match (path, arg) {
-        (Some(path), _) => {
-            let path = resolve_path::PathResolveExt::try_resolve(&path)?;
-            Ok(std::fs::read_to_string(path)?)
+/// Resolves a secret given either as the path to its file or as a source.
+fn file_or_secret(path: Option<String>, path_name: &str, arg: Option<SecretSource>, arg_name: &str) -> Result<Secret, config::ConfigError> {
+    let source = match (path, arg) {
+        (Some(path), _) => SecretSource::File(path),
+        (_, Some(arg)) => arg,
+        _ => return Err(config::ConfigError::Conflict(format!(r#"must specify either {} or {}"#, path_name, arg_name))),
+    };
+    Ok(source.resolve()?)
+}
+
+fn token_store(workdir: &str, passphrase: Option<PassphraseSource>) -> TokenStore {
+    TokenStore::new(std::path::Path::new(workdir).join("token.bin"), passphrase)
+}
+
+fn template(source: &str, name: &str) -> Result<Template, config::ConfigError> {
+    Template::parse(source).map_err(|err| config::ConfigError::Conflict(format!("invalid {}: {}", name, err)))
+}
+
+fn presence_templates(config: &ArgumentConfig) -> Result<discord::PresenceTemplates, config::ConfigError> {
+    if config.button_label.len() != config.button_url.len() {
+        return Err(config::ConfigError::Conflict(String::from("button-label and button-url must be given the same number of times")));
+    }
+    if config.button_label.len() > discord::MAX_BUTTONS {
+        return Err(config::ConfigError::Conflict(format!("at most {} buttons are allowed", discord::MAX_BUTTONS)));
+    }
+    Ok(discord::PresenceTemplates {
+        details: template(&config.details, "details")?,
+        state: template(&config.state, "state")?,
+        large_text: template(&config.large_text, "large-text")?,
+        small_image: config.small_image.clone(),
+        small_text: template(&config.small_text, "small-text")?,
+        buttons: config.button_label.iter().zip(&config.button_url)
+            .map(|(label, url)| Ok((template(label, "button-label")?, template(url, "button-url")?)))
+            .collect::<Result<_, config::ConfigError>>()?,
+    })
+}
+
+fn init_error(err: lastfm::InitError, api_key: &str) -> String {
+    match err {
+        lastfm::InitError::Internal(err) => format!("internal error: {}", err),
+        lastfm::InitError::BadStateFile(err) => format!("error with state file: {}", err,),
+        lastfm::InitError::NeedAuth(auth_token) => {
+            lastfm::eprint_auth_request(api_key, &auth_token);
+            String::from("unauthorized")
+        }
+        lastfm::InitError::IoError(err) => format!("io error: {}", err),
+        lastfm::InitError::RequestError(prost_lastfm::error::Error::LastFM(err)) => {
+            format!("error response from server: {}", err)
         }
-        (_, Some(arg)) => Ok(arg),
-        _ => Err(config::ConfigError::Conflict(format!(r#"must specify either {} or {}"#, path_name, arg_name))),
+        lastfm::InitError::RequestError(err) => format!("request error: {}", err),
+        lastfm::InitError::TokenError(err) => format!("error with token file: {}", err),
     }
 }
 
 struct ProgramConfig {
     workdir: String,
     query_interval: u64,
-    discord_app_id: String,
-    lastfm_api_key: String,
-    lastfm_secret: String,
+    max_backoff: u64,
+    source: Vec<Source>,
+    #[cfg(feature = "mpris")]
+    mpris_filter: eclect::mpris::PlayerFilter,
+    mpd_address: eclect::mpd::MpdAddress,
+    mpd_password: Option<Secret>,
+    reassert_interval: u64,
+    presence: discord::PresenceTemplates,
+    discord_app_id: Secret,
+    lastfm_api_key: Secret,
+    lastfm_secret: Secret,
+    endpoint: String,
+    auth: AuthConfig,
+    token_passphrase: Option<PassphraseSource>,
+}
+
+/// The flags for getting a session. They are only turned into an
+/// [`AuthMethod`] once one is needed, so the password file is left unread
+/// while there is a session.
+struct AuthConfig {
+    username: Option<String>,
+    password_file: Option<String>,
+    web_auth_port: Option<u16>,
+    open_browser: bool,
+}
+
+impl AuthConfig {
+    fn method(&self) -> Result<AuthMethod, config::ConfigError> {
+        if let (Some(username), Some(path)) = (&self.username, &self.password_file) {
+            let path = resolve_path::PathResolveExt::try_resolve(path)?;
+            return Ok(AuthMethod::Mobile {
+                username: username.clone(),
+                password: std::fs::read_to_string(path)?.trim_end().to_string(),
+            });
+        }
+        Ok(match self.web_auth_port {
+            Some(port) => AuthMethod::Web { port, open_browser: self.open_browser },
+            None => AuthMethod::Desktop { open_browser: self.open_browser },
+        })
+    }
+}
+
+/// How to get a Last.fm session when there is none.
+enum AuthMethod {
+    /// Wait while the user approves access in a browser.
+    Desktop { open_browser: bool },
+    /// Wait on 127.0.0.1 for the browser to come back from approving access.
+    Web { port: u16, open_browser: bool },
+    /// Log in with a username and password.
+    Mobile { username: String, password: String },
 }
 
 impl ArgumentConfig {
+    fn lastfm_credentials(&self) -> Result<(Secret, Secret), config::ConfigError> {
+        Ok((
+            file_or_secret(self.lastfm_api_key_file.clone(), "lastfm-api-key-file", self.lastfm_api_key.clone(), "lastfm-api-key")?,
+            file_or_secret(self.lastfm_secret_file.clone(), "lastfm-secret-file", self.lastfm_secret.clone(), "lastfm-secret")?,
+        ))
+    }
+
+    fn discord_app_id(&self) -> Result<Secret, config::ConfigError> {
+        file_or_secret(self.discord_app_id_file.clone(), "discord-app-id-file", self.discord_app_id.clone(), "discord-app-id")
+    }
+
+    fn token_passphrase(&self) -> Result<Option<PassphraseSource>, config::ConfigError> {
+        Ok(match (&self.token_passphrase_file, &self.token_passphrase_command) {
+            (Some(path), _) => Some(PassphraseSource::File(resolve_path::PathResolveExt::try_resolve(path)?.into_owned())),
+            (_, Some(command)) => Some(PassphraseSource::Command(command.clone())),
+            (None, None) => None,
+        })
+    }
+
+    fn auth_config(&self) -> AuthConfig {
+        AuthConfig {
+            username: self.username.clone(),
+            password_file: self.password_file.clone(),
+            web_auth_port: self.web_auth_port,
+            open_browser: self.open_browser,
+        }
+    }
+
     fn resolve(self) -> Result<ProgramConfig, config::ConfigError> {
+        let (lastfm_api_key, lastfm_secret) = self.lastfm_credentials()?;
+        let auth = self.auth_config();
+        let token_passphrase = self.token_passphrase()?;
         Ok(ProgramConfig {
+            discord_app_id: self.discord_app_id()?,
+            presence: presence_templates(&self)?,
             workdir: self.workdir,
             query_interval: self.query_interval,
-            discord_app_id: file_or_string(self.discord_app_id_file, "discord-app-id-file", self.discord_app_id, "discord-app-id")?,
-            lastfm_api_key: file_or_string(self.lastfm_api_key_file, "lastfm-api-key-file", self.lastfm_api_key, "lastfm-api-key")?,
-            lastfm_secret: file_or_string(self.lastfm_secret_file, "lastfm-secret-file", self.lastfm_secret, "lastfm-secret")?,
+            max_backoff: self.max_backoff,
+            source: self.source,
+            #[cfg(feature = "mpris")]
+            mpris_filter: eclect::mpris::PlayerFilter {
+                allow: self.mpris_allow,
+                deny: self.mpris_deny,
+            },
+            mpd_address: self.mpd_address.parse().map_err(|err| config::ConfigError::Conflict(format!("invalid mpd-address: {}", err)))?,
+            reassert_interval: self.reassert_interval,
+            mpd_password: match (self.mpd_password_file, self.mpd_password) {
+                (None, None) => None,
+                (path, arg) => Some(file_or_secret(path, "mpd-password-file", arg, "mpd-password")?),
+            },
+            lastfm_api_key,
+            lastfm_secret,
+            endpoint: self.endpoint,
+            auth,
+            token_passphrase,
         })
     }
 }
 
-fn main() -> Result<(), String> {
-    let base_config: Config<ArgumentConfig> = Config::parse();
-    let ProgramConfig {
-        workdir,
-        query_interval,
-        discord_app_id,
-        lastfm_api_key,
-        lastfm_secret,
-    } = base_config
-        .inner
-        .resolve()
-        .map_err(|err| {
-            Config::<ArgumentConfig>::command().error(
-                clap::error::ErrorKind::InvalidValue,
-                format!("configuration error: {}", err),
-            ).exit()
-        }).unwrap();
-    let work_path = std::path::Path::new(&workdir);
+fn config_error(err: config::ConfigError) -> ! {
+    Cli::command().error(
+        clap::error::ErrorKind::InvalidValue,
+        format!("configuration error: {}", err),
+    ).exit()
+}
+
+/// Creates the workdir if it does not exist yet.
+fn open_workdir(workdir: &str) -> Result<&std::path::Path, String> {
+    let work_path = std::path::Path::new(workdir);
     if work_path.exists() && !work_path.is_dir() {
         return Err(format!(
             "error accessing data: workdir {} is not a directory",
-            &workdir
+            workdir
         ));
     }
     if !work_path.exists() {
@@ -111,46 +354,359 @@ fn main() -> Result<(), String> {
             return Err(format!("error creating workdir: {}", err));
         }
     }
+    Ok(work_path)
+}
 
-    let mut discord_client =
-        discord::activate(&discord_app_id).map_err(|err| format!("discord ipc error: {}", err))?;
-
-    let token_path = work_path.join("token.bin");
-    let client = reqwest::blocking::Client::builder()
+fn http_client() -> reqwest::blocking::Client {
+    reqwest::blocking::Client::builder()
         .user_agent(USER_AGENT)
         .build()
-        .unwrap();
-    let (agent, session_key) =
-        lastfm::activate_session(client.clone(), &lastfm_api_key, &lastfm_secret, &token_path)
-            .map_err(|err| match err {
-                lastfm::InitError::Internal(err) => format!("internal error: {}", err),
-                lastfm::InitError::BadStateFile(err) => format!("error with state file: {}", err,),
-                lastfm::InitError::NeedAuth(auth_token) => {
-                    lastfm::eprint_auth_request(&lastfm_api_key, &auth_token);
-                    String::from("unauthorized")
-                }
-                lastfm::InitError::IoError(err) => format!("io error: {}", err),
-                lastfm::InitError::ReqwestError(err) => format!("request error: {}", err),
-                lastfm::InitError::LastFMError(err) => {
-                    format!("error response from server: {}", err)
-                }
-            })?;
+        .unwrap()
+}
+
+fn main() -> Result<(), String> {
+    let cli = Cli::parse();
+    match cli.command.unwrap_or(Command::Run(cli.run)) {
+        Command::Run(config) => run(config.inner.resolve().unwrap_or_else(|err| config_error(err))),
+        Command::Auth(config) => auth(config.inner),
+        Command::Logout(config) => logout(config.inner),
+        Command::Status(config) => status(config.inner),
+        Command::Whoami(config) => whoami(config.inner),
+    }
+}
+
+fn auth(config: ArgumentConfig) -> Result<(), String> {
+    let (api_key, secret) = config.lastfm_credentials().unwrap_or_else(|err| config_error(err));
+    open_workdir(&config.workdir)?;
+    let passphrase = config.token_passphrase().unwrap_or_else(|err| config_error(err));
+    let tokens = token_store(&config.workdir, passphrase);
+    if tokens.exists() && let lastfm::InitToken::Session(_) = tokens.read().map_err(|err| init_error(err.into(), api_key.expose()))? {
+        println!("Already authorized. Run logout first to authorize again.");
+        return Ok(());
+    }
+    let method = config.auth_config().method().unwrap_or_else(|err| config_error(err));
+    let client = http_client();
+    authorize(&client, &config.endpoint, api_key.expose(), secret.expose(), &tokens, &method, None)?;
+    lastfm::activate_session(client, &config.endpoint, api_key.expose(), secret.expose(), &tokens)
+        .map(|_| ())
+        .map_err(|err| init_error(err, api_key.expose()))
+}
+
+fn prompt_auth(url: &url::Url, open_browser: bool) {
+    eprintln!("Navigate to {} to authorize this application. Waiting for approval...", url.as_str());
+    if open_browser && let Err(err) = open::that(url.as_str()) {
+        eprintln!("Error opening browser: {}", err);
+    }
+}
+
+/// Gets and saves a session. Desktop authorization uses `auth_token` if
+/// given, or a new one.
+fn authorize(
+    client: &reqwest::blocking::Client,
+    endpoint: &str,
+    api_key: &str,
+    secret: &str,
+    tokens: &TokenStore,
+    method: &AuthMethod,
+    auth_token: Option<String>,
+) -> Result<(), String> {
+    let agent = prost_lastfm::AuthServiceAgent::new(client.clone(), api_key.to_string(), secret.to_string(), endpoint.to_string());
+    match method {
+        AuthMethod::Desktop { open_browser } => {
+            let auth_token = match auth_token {
+                Some(auth_token) => auth_token,
+                None => match lastfm::restart_auth(client.clone(), endpoint, api_key, secret, tokens) {
+                    lastfm::InitError::NeedAuth(auth_token) => auth_token,
+                    err => return Err(init_error(err, api_key)),
+                },
+            };
+            lastfm::wait_for_session(&agent, tokens, auth_token, lastfm::AUTH_POLL_INTERVAL, |auth_token| {
+                prompt_auth(&lastfm::build_auth_request(api_key, auth_token), *open_browser)
+            })
+            .map_err(|err| init_error(err, api_key))?;
+        }
+        AuthMethod::Web { port, open_browser } => authorize_web(&agent, api_key, tokens, *port, *open_browser)?,
+        AuthMethod::Mobile { username, password } => {
+            lastfm::mobile_session(&agent, tokens, username, password).map_err(|err| init_error(err, api_key))?;
+        }
+    }
+    println!("Authorized");
+    Ok(())
+}
+
+/// Sends the user through web authorization, and waits on 127.0.0.1 for the
+/// browser to come back with a token.
+fn authorize_web(
+    agent: &prost_lastfm::AuthServiceAgent,
+    api_key: &str,
+    tokens: &TokenStore,
+    port: u16,
+    open_browser: bool,
+) -> Result<(), String> {
+    let listener = eclect::callback::CallbackListener::bind(port)
+        .map_err(|err| format!("error listening for authorization callback: {}", err))?;
+    let callback_url = listener.url().map_err(|err| format!("io error: {}", err))?;
+    prompt_auth(&lastfm::build_web_auth_request(api_key, &callback_url), open_browser);
+    let callback = listener.accept(eclect::callback::ACCEPT_TIMEOUT).map_err(|err| match err.kind() {
+        std::io::ErrorKind::TimedOut => String::from("timed out waiting for authorization, run auth again"),
+        _ => format!("error accepting authorization callback: {}", err),
+    })?;
+    let exchanged = lastfm::exchange_token(agent, tokens, &callback.token).map_err(|err| init_error(err, api_key));
+    if let Err(err) = callback.finish(exchanged.as_ref().map(|_| ()).map_err(String::as_str)) {
+        println!("Error answering authorization callback: {}", err);
+    }
+    exchanged.map(|_| ())
+}
+
+fn logout(config: ArgumentConfig) -> Result<(), String> {
+    let tokens = token_store(&config.workdir, None);
+    match std::fs::remove_file(tokens.path()) {
+        Ok(()) => {
+            println!("Logged out. Last.fm keeps the session valid until it is revoked at {}", lastfm::APPLICATIONS_URL);
+            Ok(())
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            println!("Not logged in");
+            Ok(())
+        }
+        Err(err) => Err(format!("error removing {}: {}", tokens.path().to_string_lossy(), err)),
+    }
+}
+
+fn status(config: ArgumentConfig) -> Result<(), String> {
+    let passphrase = config.token_passphrase().unwrap_or_else(|err| config_error(err));
+    let tokens = token_store(&config.workdir, passphrase);
+    let token = if tokens.exists() {
+        match tokens.read() {
+            Ok(lastfm::InitToken::Session(_)) => String::from("authorized"),
+            Ok(lastfm::InitToken::Auth(_)) => String::from("waiting for approval, run auth to finish"),
+            Err(err @ (TokenError::Io(_) | TokenError::Permissions(_) | TokenError::Passphrase(_))) => err.to_string(),
+            Err(err) => format!("{}, run logout then auth", err),
+        }
+    } else {
+        String::from("not authorized, run auth")
+    };
+    println!("Last.fm: {}", token);
+    let discord = match config.discord_app_id() {
+        Ok(app_id) => match discord_rich_presence::DiscordIpcClient::new(app_id.expose())
+            .and_then(|mut client| discord::probe(&mut client))
+        {
+            Ok(()) => String::from("connected"),
+            Err(err) => format!("unavailable ({})", err),
+        },
+        Err(err) => format!("not configured ({})", err),
+    };
+    println!("Discord: {}", discord);
+    Ok(())
+}
+
+fn whoami(config: ArgumentConfig) -> Result<(), String> {
+    let (api_key, secret) = config.lastfm_credentials().unwrap_or_else(|err| config_error(err));
+    let passphrase = config.token_passphrase().unwrap_or_else(|err| config_error(err));
+    let session_key = match token_store(&config.workdir, passphrase).read() {
+        Ok(lastfm::InitToken::Session(session_key)) => session_key,
+        Ok(lastfm::InitToken::Auth(_)) => return Err(String::from("not logged in: waiting for approval, run auth to finish")),
+        Err(TokenError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Err(String::from("not logged in: run auth"));
+        }
+        Err(err) => return Err(init_error(err.into(), api_key.expose())),
+    };
+    let agent = prost_lastfm::LastFmServiceAgent::new(http_client(), api_key.expose().to_string(), secret.expose().to_string(), config.endpoint.clone());
+    let user = prost_lastfm::LastFmService::user_get_info(&agent, prost_lastfm::user::GetInfoRequest { user: None }, Some(&session_key))
+        .map_err(|err| init_error(err.into(), api_key.expose()))?
+        .user
+        .ok_or_else(|| String::from("user request error: empty response"))?;
+    println!("{} ({})", user.name, user.url);
+    if let Some(playcount) = user.playcount {
+        println!("{} scrobbles", playcount);
+    }
+    Ok(())
+}
+
+fn run(config: ProgramConfig) -> Result<(), String> {
+    let ProgramConfig {
+        workdir,
+        query_interval,
+        max_backoff,
+        source,
+        #[cfg(feature = "mpris")]
+        mpris_filter,
+        mpd_address,
+        mpd_password,
+        reassert_interval,
+        presence,
+        discord_app_id,
+        lastfm_api_key,
+        lastfm_secret,
+        endpoint,
+        auth,
+        token_passphrase,
+    } = config;
+    let work_path = open_workdir(&workdir)?;
+
+    let discord_client = discord_rich_presence::DiscordIpcClient::new(discord_app_id.expose())
+        .map_err(|err| format!("discord ipc error: {}", err))?;
+    // Discord is connected in the loop, so it may start after this program.
+    let mut discord = discord::DiscordConnection::new(
+        discord_client,
+        Backoff::new(discord::RETRY_DELAY, discord::MAX_RETRY_DELAY),
+    );
+
+    let tokens = token_store(&workdir, token_passphrase);
+    let client = http_client();
+    let activate = || lastfm::activate_session(client.clone(), &endpoint, lastfm_api_key.expose(), lastfm_secret.expose(), &tokens);
+    let (agent, session_key, user) = match activate() {
+        Err(lastfm::InitError::NeedAuth(auth_token)) => {
+            let method = auth.method().unwrap_or_else(|err| config_error(err));
+            authorize(&client, &endpoint, lastfm_api_key.expose(), lastfm_secret.expose(), &tokens, &method, Some(auth_token))?;
+            activate()
+        }
+        activated => activated,
+    }
+    .map_err(|err| init_error(err, lastfm_api_key.expose()))?;
+    let mut scrobble_queue = prost_lastfm::queue::ScrobbleQueue::open(work_path.join("scrobbles.json"))
+        .map_err(|err| format!("error opening scrobble queue: {}", err))?;
+    let mut track_sources: Vec<Box<dyn TrackSource>> = Vec::new();
+    for source in source {
+        match source {
+            Source::Lastfm => track_sources.push(Box::new(lastfm::LastFmSource::new(
+                prost_lastfm::LastFmServiceAgent::new(
+                    client.clone(),
+                    lastfm_api_key.expose().to_string(),
+                    lastfm_secret.expose().to_string(),
+                    endpoint.clone(),
+                ),
+                session_key.clone(),
+                Backoff::new(Duration::from_secs(query_interval), Duration::from_secs(max_backoff)),
+            ))),
+            #[cfg(feature = "mpris")]
+            Source::Mpris => match eclect::mpris::MprisWatcher::session(mpris_filter.clone()) {
+                Ok(watcher) => track_sources.push(Box::new(watcher)),
+                Err(err) => println!("Skipping mpris source: d-bus error: {}", err),
+            },
+            Source::Mpd => track_sources.push(Box::new(eclect::mpd::MpdWatcher::new(
+                mpd_address.clone(),
+                mpd_password.as_ref().map(|password| password.expose().to_string()),
+            ))),
+        }
+    }
+    if track_sources.is_empty() {
+        return Err(String::from("no track sources available"));
+    }
+    let mut sources = Sources::spawn(track_sources, Duration::from_secs(query_interval));
+    let interrupter = sources.interrupter();
+    let mut interrupted = false;
+    ctrlc::set_handler(move || {
+        // A second signal means the user does not want to wait.
+        if interrupted {
+            std::process::exit(130);
+        }
+        interrupted = true;
+        interrupter.interrupt();
+    })
+    .map_err(|err| format!("error setting signal handler: {}", err))?;
+    let mut playcounts = presence.uses("playcount").then(|| {
+        lastfm::PlaycountLookup::new(
+            prost_lastfm::LastFmServiceAgent::new(
+                client.clone(),
+                lastfm_api_key.expose().to_string(),
+                lastfm_secret.expose().to_string(),
+                endpoint.clone(),
+            ),
+            session_key.clone(),
+            user.name.clone(),
+        )
+    });
+    // Errors Last.fm will keep returning until the user steps in.
+    let check_fatal = |err: &prost_lastfm::error::Error| match Severity::of(err) {
+        Severity::Fatal => Err(format!("last.fm rejected the api key or secret: {}", err)),
+        Severity::Reauth => Err(init_error(
+            lastfm::restart_auth(client.clone(), &endpoint, lastfm_api_key.expose(), lastfm_secret.expose(), &tokens),
+            lastfm_api_key.expose(),
+        )),
+        _ => Ok(()),
+    };
+    let mut play_clock = discord::PlayClock::default();
+    let mut presence = discord::Presence::new(presence, Duration::from_secs(reassert_interval));
 
     loop {
-        let track = lastfm::now_playing(&agent, Some(&session_key));
-        match track {
-            Err(err) => println!("Error querying now playing: {}", err),
-            Ok(track) => match discord::set_track(&mut discord_client, track) {
-                Ok(None) => println!("No track playing"),
-                Ok(Some(desc)) => println!("Now playing: {}", desc),
-                Err(err) => {
-                    println!("Error setting activity: {}", err);
-                    discord_client
-                        .reconnect()
-                        .map_err(|err| format!("discord error: {}", err))?
+        let interval = Duration::from_secs(query_interval);
+        let timeout = discord
+            .until_retry(Instant::now())
+            .map_or(interval, |retry| retry.min(interval));
+        let event = sources.recv_timeout(timeout);
+        if let Some(SourceEvent::Interrupted) = event {
+            break;
+        }
+        if !scrobble_queue.is_empty() {
+            match scrobble_queue.flush(&agent, &session_key) {
+                Err(err) => println!("Error saving scrobble queue: {}", err),
+                Ok(report) => {
+                    println!(
+                        "Submitted queued scrobbles: {} accepted, {} ignored, {} dropped, {} unconfirmed, {} pending",
+                        report.accepted,
+                        report.ignored,
+                        report.dropped,
+                        report.unconfirmed,
+                        scrobble_queue.len()
+                    );
+                    if let Some(err) = report.error {
+                        println!("Error submitting scrobbles: {}", err);
+                        check_fatal(&err)?;
+                    }
                 }
-            },
+            }
+        }
+        if let Some(SourceEvent::Error(name, err)) = event {
+            println!("Error reading {}: {}", name, err);
+            if let SourceError::LastFM(err) = &err {
+                check_fatal(err)?;
+            }
+        }
+        let playing = sources
+            .current()
+            .filter(|state| state.status == PlaybackStatus::Playing);
+        // The clock also picks up repeats that the source could not report.
+        let times = playing.map(|state| play_clock.times(state, SystemTime::now()));
+        let track = playing.map(|state| &state.track);
+        let values = track
+            .map(|track| {
+                let playcount = playcounts.as_mut().and_then(|lookup| lookup.playcount(track));
+                discord::track_values(track, Some(&user.name), playcount)
+            })
+            .unwrap_or_default();
+        match discord.maintain(Instant::now()) {
+            Ok(true) => {
+                println!("Connected to Discord");
+                presence.invalidate();
+            }
+            Ok(false) => {}
+            Err(err) => println!(
+                "Discord unavailable ({}), retrying in {}s",
+                err,
+                discord.until_retry(Instant::now()).unwrap_or_default().as_secs()
+            ),
         }
-        std::thread::sleep(Duration::from_secs(query_interval));
+        let Some(discord_client) = discord.client() else {
+            continue;
+        };
+        match presence.update(discord_client, track, &values, times, Instant::now()) {
+            Ok(discord::PresenceUpdate::Unchanged) => {}
+            Ok(discord::PresenceUpdate::Cleared) => println!("No track playing"),
+            Ok(discord::PresenceUpdate::Set(desc)) => println!("Now playing: {}", desc),
+            Err(err) => {
+                println!("Error setting activity, reconnecting: {}", err);
+                discord.disconnect();
+                presence.invalidate();
+            }
+        }
+    }
+
+    println!("Shutting down");
+    if let Err(err) = discord.shutdown() {
+        println!("Error clearing activity: {}", err);
     }
+    scrobble_queue
+        .save()
+        .map_err(|err| format!("error saving scrobble queue: {}", err))
 }
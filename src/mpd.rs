@@ -0,0 +1,390 @@
+use crate::player::{PlaybackStatus, PlayerState};
+use crate::source::{SourceError, TrackSource};
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+pub const DEFAULT_PORT: u16 = 6600;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, into_enum::IntoEnum)]
+pub enum MpdError {
+    Io(std::io::Error),
+    /// The server sent something that is not MPD protocol.
+    #[into_enum(skip)]
+    Protocol(String),
+    /// The server rejected a command with an `ACK` line.
+    #[into_enum(skip)]
+    Ack(String),
+}
+
+impl Display for MpdError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MpdError::Io(err) => write!(f, "MpdError(Io) {{ {} }}", err),
+            MpdError::Protocol(msg) => write!(f, "MpdError(Protocol) {{ {} }}", msg),
+            MpdError::Ack(msg) => write!(f, "MpdError(Ack) {{ {} }}", msg),
+        }
+    }
+}
+
+impl std::error::Error for MpdError {}
+
+/// Where the MPD server listens.
+///
+/// Parsed from `host`, `host:port`, or on Unix an absolute path to a socket.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MpdAddress {
+    Tcp(String, u16),
+    #[cfg(unix)]
+    Unix(std::path::PathBuf),
+}
+
+impl std::str::FromStr for MpdAddress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        #[cfg(unix)]
+        if s.starts_with('/') {
+            return Ok(MpdAddress::Unix(s.into()));
+        }
+        // Bracketed IPv6 addresses contain colons of their own.
+        let (host, port) = match s.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => (
+                host,
+                port.parse()
+                    .map_err(|err| format!("invalid port in {}: {}", s, err))?,
+            ),
+            _ => (s, DEFAULT_PORT),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(format!("missing host in {}", s));
+        }
+        Ok(MpdAddress::Tcp(host.to_string(), port))
+    }
+}
+
+impl Display for MpdAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MpdAddress::Tcp(host, port) if host.contains(':') => write!(f, "[{}]:{}", host, port),
+            MpdAddress::Tcp(host, port) => write!(f, "{}:{}", host, port),
+            #[cfg(unix)]
+            MpdAddress::Unix(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixStream),
+}
+
+impl Stream {
+    fn connect(address: &MpdAddress) -> std::io::Result<Self> {
+        match address {
+            MpdAddress::Tcp(host, port) => {
+                let mut last_err = None;
+                for addr in std::net::ToSocketAddrs::to_socket_addrs(&(host.as_str(), *port))? {
+                    match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+                        Ok(stream) => return Ok(Stream::Tcp(stream)),
+                        Err(err) => last_err = Some(err),
+                    }
+                }
+                Err(last_err.unwrap_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::NotFound, "host has no addresses")
+                }))
+            }
+            #[cfg(unix)]
+            MpdAddress::Unix(path) => {
+                Ok(Stream::Unix(std::os::unix::net::UnixStream::connect(path)?))
+            }
+        }
+    }
+
+    fn try_clone(&self) -> std::io::Result<Self> {
+        match self {
+            Stream::Tcp(stream) => stream.try_clone().map(Stream::Tcp),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.try_clone().map(Stream::Unix),
+        }
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.set_read_timeout(timeout),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Quotes a command argument.
+fn quote(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn is_timeout(err: &std::io::Error) -> bool {
+    matches!(
+        err.kind(),
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+    )
+}
+
+/// One connection to an MPD server.
+pub struct MpdConnection {
+    reader: BufReader<Stream>,
+    writer: Stream,
+    /// A line cut off by a read timeout.
+    line: String,
+}
+
+impl MpdConnection {
+    /// Connects and checks the server greeting, then sends `password` if set.
+    pub fn connect(address: &MpdAddress, password: Option<&str>) -> Result<Self, MpdError> {
+        let writer = Stream::connect(address)?;
+        let mut conn = Self {
+            reader: BufReader::new(writer.try_clone()?),
+            writer,
+            line: String::new(),
+        };
+        let greeting = conn.read_line()?;
+        if !greeting.starts_with("OK MPD ") {
+            return Err(MpdError::Protocol(format!(
+                "unexpected greeting: {}",
+                greeting
+            )));
+        }
+        if let Some(password) = password {
+            conn.command(&format!("password {}", quote(password)))?;
+        }
+        Ok(conn)
+    }
+
+    fn read_line(&mut self) -> Result<String, MpdError> {
+        if self.reader.read_line(&mut self.line)? == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        let line = std::mem::take(&mut self.line);
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    /// Reads `key: value` lines up to the closing `OK`.
+    fn read_response(&mut self) -> Result<Vec<(String, String)>, MpdError> {
+        let mut pairs = Vec::new();
+        loop {
+            let line = self.read_line()?;
+            if line == "OK" {
+                return Ok(pairs);
+            }
+            if let Some(ack) = line.strip_prefix("ACK ") {
+                return Err(MpdError::Ack(ack.to_string()));
+            }
+            let Some((key, value)) = line.split_once(": ") else {
+                return Err(MpdError::Protocol(format!("unexpected line: {}", line)));
+            };
+            pairs.push((key.to_string(), value.to_string()));
+        }
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), MpdError> {
+        self.writer.write_all(format!("{}\n", command).as_bytes())?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Sends a command and returns its response.
+    pub fn command(&mut self, command: &str) -> Result<Vec<(String, String)>, MpdError> {
+        self.send(command)?;
+        self.read_response()
+    }
+
+    /// Waits for the player to change with `idle player`. Returns whether it
+    /// changed before `timeout` passed.
+    pub fn idle_player(&mut self, timeout: Duration) -> Result<bool, MpdError> {
+        self.send("idle player")?;
+        self.reader.get_ref().set_read_timeout(Some(timeout))?;
+        let response = self.read_response();
+        self.reader.get_ref().set_read_timeout(None)?;
+        match response {
+            Ok(changed) => Ok(!changed.is_empty()),
+            Err(MpdError::Io(err)) if is_timeout(&err) => {
+                // The server answers noidle with the changes seen so far.
+                self.send("noidle")?;
+                Ok(!self.read_response()?.is_empty())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Reads the player state from `status` and `currentsong`.
+    pub fn player_state(&mut self, player: &str) -> Result<PlayerState, MpdError> {
+        let status = self.command("status")?;
+        let song = self.command("currentsong")?;
+        Ok(build_state(player, &status, &song))
+    }
+}
+
+fn field<'a>(pairs: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    pairs
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        .map(|(_, v)| v.as_str())
+        .filter(|v| !v.is_empty())
+}
+
+fn seconds(value: &str) -> Option<Duration> {
+    value
+        .parse::<f64>()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+}
+
+fn build_state(
+    player: &str,
+    status: &[(String, String)],
+    song: &[(String, String)],
+) -> PlayerState {
+    let playback = match field(status, "state") {
+        Some("play") => PlaybackStatus::Playing,
+        Some("pause") => PlaybackStatus::Paused,
+        _ => PlaybackStatus::Stopped,
+    };
+    // Servers before 0.20 only send `time: elapsed:total` with whole seconds.
+    let time = field(status, "time").and_then(|time| time.split_once(':'));
+    let position = field(status, "elapsed")
+        .or(time.map(|(elapsed, _)| elapsed))
+        .and_then(seconds);
+    let length = field(status, "duration")
+        .or(field(song, "duration"))
+        .or(field(song, "Time"))
+        .or(time.map(|(_, total)| total))
+        .and_then(seconds);
+
+    let artist = song
+        .iter()
+        .filter(|(key, _)| key == "Artist")
+        .map(|(_, value)| value.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    // Untagged files fall back to their name.
+    let title = field(song, "Title")
+        .or(field(song, "Name"))
+        .or(field(song, "file").map(|file| {
+            let name = file.rsplit('/').next().unwrap_or(file);
+            name.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(name)
+        }))
+        .unwrap_or_default();
+    PlayerState {
+        player: player.to_string(),
+        status: playback,
+        track: crate::player::build_track(title, &artist, field(song, "Album"), None),
+        length,
+        position,
+    }
+}
+
+/// Follows an MPD server, reconnecting after the connection drops.
+pub struct MpdWatcher {
+    address: MpdAddress,
+    password: Option<String>,
+    conn: Option<MpdConnection>,
+}
+
+impl MpdWatcher {
+    /// Connects on first use.
+    pub fn new(address: MpdAddress, password: Option<String>) -> Self {
+        Self {
+            address,
+            password,
+            conn: None,
+        }
+    }
+
+    pub fn address(&self) -> &MpdAddress {
+        &self.address
+    }
+
+    /// Runs `f` on the connection, reconnecting once if a connection left
+    /// over from before fails. The connection is dropped after any error so
+    /// the next call starts fresh.
+    fn with_connection<T>(
+        &mut self,
+        mut f: impl FnMut(&mut MpdConnection) -> Result<T, MpdError>,
+    ) -> Result<T, MpdError> {
+        if let Some(conn) = &mut self.conn {
+            match f(conn) {
+                Ok(value) => return Ok(value),
+                Err(MpdError::Io(_)) => self.conn = None,
+                Err(err) => {
+                    self.conn = None;
+                    return Err(err);
+                }
+            }
+        }
+        let mut conn = MpdConnection::connect(&self.address, self.password.as_deref())?;
+        let value = f(&mut conn)?;
+        self.conn = Some(conn);
+        Ok(value)
+    }
+
+    /// The server's player, or `None` if nothing is loaded.
+    pub fn current(&mut self) -> Result<Option<PlayerState>, MpdError> {
+        let player = format!("mpd {}", self.address);
+        let state = self.with_connection(|conn| conn.player_state(&player))?;
+        Ok((state.status != PlaybackStatus::Stopped).then_some(state))
+    }
+
+    /// Blocks until the player changes or `timeout` passes. Returns whether a
+    /// change was seen.
+    pub fn wait(&mut self, timeout: Duration) -> Result<bool, MpdError> {
+        self.with_connection(|conn| conn.idle_player(timeout))
+    }
+}
+
+impl TrackSource for MpdWatcher {
+    fn name(&self) -> String {
+        format!("mpd {}", self.address)
+    }
+
+    fn current(&mut self) -> Result<Option<PlayerState>, SourceError> {
+        Ok(MpdWatcher::current(self)?)
+    }
+
+    fn wait(&mut self, timeout: Duration) -> Result<(), SourceError> {
+        MpdWatcher::wait(self, timeout)?;
+        Ok(())
+    }
+}
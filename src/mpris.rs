@@ -0,0 +1,243 @@
+use crate::player::{PlaybackStatus, PlayerState};
+use crate::source::{SourceError, TrackSource};
+use dbus::arg::{PropMap, RefArg};
+use dbus::blocking::Connection;
+use dbus::blocking::stdintf::org_freedesktop_dbus::Properties;
+use dbus::message::MatchRule;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+pub const BUS_NAME_PREFIX: &str = "org.mpris.MediaPlayer2.";
+pub const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+pub const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+const CALL_TIMEOUT: Duration = Duration::from_secs(2);
+
+fn parse_status(status: &str) -> PlaybackStatus {
+    match status {
+        "Playing" => PlaybackStatus::Playing,
+        "Paused" => PlaybackStatus::Paused,
+        _ => PlaybackStatus::Stopped,
+    }
+}
+
+/// Selects players by the part of their bus name after `org.mpris.MediaPlayer2.`.
+///
+/// A pattern matches its name exactly and any instance of it, so `firefox`
+/// matches `firefox.instance_1_42`. Deny patterns win over allow patterns. An
+/// empty allow list allows every player.
+#[derive(Clone, Debug, Default)]
+pub struct PlayerFilter {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+impl PlayerFilter {
+    fn pattern_matches(pattern: &str, name: &str) -> bool {
+        name == pattern
+            || name
+                .strip_prefix(pattern)
+                .is_some_and(|rest| rest.starts_with('.'))
+    }
+
+    pub fn allows(&self, bus_name: &str) -> bool {
+        let Some(name) = bus_name.strip_prefix(BUS_NAME_PREFIX) else {
+            return false;
+        };
+        if self
+            .deny
+            .iter()
+            .any(|pattern| Self::pattern_matches(pattern, name))
+        {
+            return false;
+        }
+        self.allow.is_empty()
+            || self
+                .allow
+                .iter()
+                .any(|pattern| Self::pattern_matches(pattern, name))
+    }
+}
+
+/// Watches MPRIS players on a D-Bus connection.
+pub struct MprisWatcher {
+    conn: Connection,
+    filter: PlayerFilter,
+    changed: Arc<AtomicBool>,
+}
+
+impl MprisWatcher {
+    /// Watches players on the session bus.
+    pub fn session(filter: PlayerFilter) -> Result<Self, dbus::Error> {
+        Self::new(Connection::new_session()?, filter)
+    }
+
+    pub fn new(conn: Connection, filter: PlayerFilter) -> Result<Self, dbus::Error> {
+        let changed = Arc::new(AtomicBool::new(true));
+
+        let properties_changed = changed.clone();
+        conn.add_match(
+            MatchRule::new_signal("org.freedesktop.DBus.Properties", "PropertiesChanged")
+                .with_path(OBJECT_PATH),
+            move |(interface,): (String,), _: &Connection, _: &dbus::Message| {
+                if interface == PLAYER_INTERFACE {
+                    properties_changed.store(true, Ordering::SeqCst);
+                }
+                true
+            },
+        )?;
+
+        // Players appearing or disappearing.
+        let owner_changed = changed.clone();
+        conn.add_match(
+            MatchRule::new_signal("org.freedesktop.DBus", "NameOwnerChanged")
+                .with_sender("org.freedesktop.DBus"),
+            move |(name,): (String,), _: &Connection, _: &dbus::Message| {
+                if name.starts_with(BUS_NAME_PREFIX) {
+                    owner_changed.store(true, Ordering::SeqCst);
+                }
+                true
+            },
+        )?;
+
+        Ok(Self {
+            conn,
+            filter,
+            changed,
+        })
+    }
+
+    /// Bus names of every player allowed by the filter, sorted.
+    pub fn players(&self) -> Result<Vec<String>, dbus::Error> {
+        let proxy = self.conn.with_proxy(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            CALL_TIMEOUT,
+        );
+        let (names,): (Vec<String>,) =
+            proxy.method_call("org.freedesktop.DBus", "ListNames", ())?;
+        let mut players = names
+            .into_iter()
+            .filter(|name| self.filter.allows(name))
+            .collect::<Vec<_>>();
+        players.sort();
+        Ok(players)
+    }
+
+    pub fn player_state(&self, player: &str) -> Result<PlayerState, dbus::Error> {
+        let proxy = self.conn.with_proxy(player, OBJECT_PATH, CALL_TIMEOUT);
+        let status: String = proxy.get(PLAYER_INTERFACE, "PlaybackStatus")?;
+        let metadata: PropMap = proxy.get(PLAYER_INTERFACE, "Metadata")?;
+        // Not every player implements Position.
+        let position = proxy
+            .get::<i64>(PLAYER_INTERFACE, "Position")
+            .ok()
+            .and_then(micros_to_duration);
+        Ok(PlayerState {
+            player: player.to_string(),
+            status: parse_status(&status),
+            track: metadata_track(&metadata),
+            length: metadata
+                .get("mpris:length")
+                .and_then(|length| {
+                    length
+                        .0
+                        .as_i64()
+                        .or_else(|| length.0.as_u64().map(|v| v as i64))
+                })
+                .and_then(micros_to_duration),
+            position,
+        })
+    }
+
+    /// The player to report: the first one playing, otherwise the first one
+    /// paused. Players that stop responding are skipped.
+    pub fn current(&self) -> Result<Option<PlayerState>, dbus::Error> {
+        let mut paused = None;
+        for player in self.players()? {
+            let Ok(state) = self.player_state(&player) else {
+                continue;
+            };
+            match state.status {
+                PlaybackStatus::Playing => return Ok(Some(state)),
+                PlaybackStatus::Paused if paused.is_none() => paused = Some(state),
+                _ => {}
+            }
+        }
+        Ok(paused)
+    }
+
+    /// Blocks until a player changes or `timeout` passes. Returns whether a
+    /// change was seen.
+    pub fn wait(&self, timeout: Duration) -> Result<bool, dbus::Error> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.changed.swap(false, Ordering::SeqCst) {
+                return Ok(true);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(false);
+            }
+            self.conn.process(deadline - now)?;
+        }
+    }
+}
+
+impl TrackSource for MprisWatcher {
+    fn name(&self) -> String {
+        String::from("mpris")
+    }
+
+    fn current(&mut self) -> Result<Option<PlayerState>, SourceError> {
+        Ok(MprisWatcher::current(self)?)
+    }
+
+    fn wait(&mut self, timeout: Duration) -> Result<(), SourceError> {
+        MprisWatcher::wait(self, timeout)?;
+        Ok(())
+    }
+}
+
+fn micros_to_duration(micros: i64) -> Option<Duration> {
+    u64::try_from(micros).ok().map(Duration::from_micros)
+}
+
+fn metadata_str<'a>(metadata: &'a PropMap, key: &str) -> Option<&'a str> {
+    metadata
+        .get(key)
+        .and_then(|value| value.0.as_str())
+        .filter(|value| !value.is_empty())
+}
+
+fn metadata_list(metadata: &PropMap, key: &str) -> Vec<String> {
+    let Some(value) = metadata.get(key) else {
+        return Vec::new();
+    };
+    // Some players send a single string instead of a list.
+    if let Some(value) = value.0.as_str() {
+        return vec![value.to_string()];
+    }
+    value
+        .0
+        .as_iter()
+        .map(|values| {
+            values
+                .filter_map(|value| value.as_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Builds a track from MPRIS metadata.
+pub fn metadata_track(metadata: &PropMap) -> prost_lastfm::Track {
+    let title = metadata_str(metadata, "xesam:title").unwrap_or_default();
+    let artist = metadata_list(metadata, "xesam:artist").join(", ");
+    crate::player::build_track(
+        title,
+        &artist,
+        metadata_str(metadata, "xesam:album"),
+        metadata_str(metadata, "mpris:artUrl"),
+    )
+}
@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaybackStatus {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+/// The state of one local player.
+#[derive(Clone, Debug)]
+pub struct PlayerState {
+    /// Identifies the player, such as its bus name or server address.
+    pub player: String,
+    pub status: PlaybackStatus,
+    pub track: prost_lastfm::Track,
+    pub length: Option<Duration>,
+    pub position: Option<Duration>,
+}
+
+impl PlayerState {
+    /// Whether this is `previous` carrying on: the same player, status and
+    /// track, with a position that has not gone backwards. Seeking back and
+    /// repeating a track do not count.
+    pub fn continues(&self, previous: &PlayerState) -> bool {
+        self.player == previous.player
+            && self.status == previous.status
+            && self.track == previous.track
+            && self.length == previous.length
+            && match (self.position, previous.position) {
+                (Some(position), Some(previous)) => position >= previous,
+                _ => true,
+            }
+    }
+}
+
+fn lastfm_url(artist: &str, title: &str) -> String {
+    let mut url = url::Url::parse("https://www.last.fm/music/").unwrap();
+    url.path_segments_mut()
+        .unwrap()
+        .pop_if_empty()
+        .extend([artist, "_", title]);
+    url.to_string()
+}
+
+/// Builds a track with the same fields Last.fm fills in, so local players can
+/// share the presence path with the Last.fm source.
+pub fn build_track(
+    title: &str,
+    artist: &str,
+    album: Option<&str>,
+    art_url: Option<&str>,
+) -> prost_lastfm::Track {
+    // Discord can only show images it can fetch.
+    let image = art_url
+        .filter(|url| url.starts_with("https://") || url.starts_with("http://"))
+        .map(|url| prost_lastfm::Image {
+            size: "medium".to_string(),
+            text: url.to_string(),
+        });
+    prost_lastfm::Track {
+        attr: Some(prost_lastfm::track::Attributes {
+            nowplaying: Some(true),
+        }),
+        name: title.to_string(),
+        album: album.map(|album| prost_lastfm::Album {
+            mbid: String::new(),
+            text: album.to_string(),
+        }),
+        artist: (!artist.is_empty()).then(|| prost_lastfm::Artist {
+            mbid: String::new(),
+            text: artist.to_string(),
+        }),
+        url: lastfm_url(artist, title),
+        image: image.into_iter().collect(),
+        mbid: String::new(),
+        streamable: false,
+    }
+}
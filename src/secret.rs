@@ -0,0 +1,153 @@
+use std::fmt::{Debug, Display, Formatter};
+use std::str::FromStr;
+
+/// A value such as an API key, shown as `[redacted]` in debug output.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+/// Describes what went wrong without the value, which may be partly read.
+#[derive(Debug)]
+pub enum SecretError {
+    /// The environment variable is unset or not unicode.
+    Env(String),
+    File(String),
+    Command(String),
+    /// The source gave nothing but whitespace.
+    Empty(String),
+}
+
+impl Display for SecretError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SecretError::Env(msg) => write!(f, "SecretError(Env) {{ {} }}", msg),
+            SecretError::File(msg) => write!(f, "SecretError(File) {{ {} }}", msg),
+            SecretError::Command(msg) => write!(f, "SecretError(Command) {{ {} }}", msg),
+            SecretError::Empty(msg) => write!(f, "SecretError(Empty) {{ {} }}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SecretError {}
+
+/// Where a secret comes from, written as `env:VAR`, `file:PATH`,
+/// `cmd:COMMAND`, or the value itself.
+///
+/// Values from files and commands are trimmed, so a trailing newline does not
+/// end up in request signatures.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SecretSource {
+    Env(String),
+    File(String),
+    /// Run through the shell, such as to ask a password manager.
+    Command(String),
+    Inline(Secret),
+}
+
+impl FromStr for SecretSource {
+    type Err = SecretError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let source = if let Some(name) = s.strip_prefix("env:") {
+            SecretSource::Env(name.to_string())
+        } else if let Some(path) = s.strip_prefix("file:") {
+            SecretSource::File(path.to_string())
+        } else if let Some(command) = s.strip_prefix("cmd:") {
+            SecretSource::Command(command.to_string())
+        } else {
+            return Ok(SecretSource::Inline(Secret::new(s)));
+        };
+        match &source {
+            SecretSource::Env(rest) | SecretSource::File(rest) | SecretSource::Command(rest)
+                if rest.is_empty() =>
+            {
+                Err(SecretError::Empty(format!(
+                    "nothing after the prefix in {}",
+                    s
+                )))
+            }
+            _ => Ok(source),
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for SecretSource {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl SecretSource {
+    pub fn resolve(&self) -> Result<Secret, SecretError> {
+        let (value, origin) = match self {
+            SecretSource::Inline(secret) => return Ok(secret.clone()),
+            SecretSource::Env(name) => (
+                std::env::var(name)
+                    .map_err(|err| SecretError::Env(format!("{}: {}", name, err)))?,
+                format!("environment variable {}", name),
+            ),
+            SecretSource::File(path) => {
+                let resolved = resolve_path::PathResolveExt::try_resolve(path)
+                    .map_err(|err| SecretError::File(format!("{}: {}", path, err)))?;
+                (
+                    std::fs::read_to_string(&resolved)
+                        .map_err(|err| SecretError::File(format!("{}: {}", path, err)))?,
+                    format!("file {}", path),
+                )
+            }
+            SecretSource::Command(command) => (
+                command_output(command).map_err(SecretError::Command)?,
+                String::from("command"),
+            ),
+        };
+        let value = value.trim();
+        if value.is_empty() {
+            return Err(SecretError::Empty(format!("{} is empty", origin)));
+        }
+        Ok(Secret::new(value))
+    }
+}
+
+/// Runs `command` through the shell and returns what it printed. Errors
+/// leave out the output.
+pub(crate) fn command_output(command: &str) -> Result<String, String> {
+    let output = shell(command)
+        .stderr(std::process::Stdio::inherit())
+        .output()
+        .map_err(|err| format!("error running command: {}", err))?;
+    if !output.status.success() {
+        return Err(format!("command failed: {}", output.status));
+    }
+    String::from_utf8(output.stdout).map_err(|_| String::from("command printed invalid utf-8"))
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> std::process::Command {
+    let mut shell = std::process::Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> std::process::Command {
+    let mut shell = std::process::Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
@@ -0,0 +1,208 @@
+use crate::player::{PlaybackStatus, PlayerState};
+use std::fmt::{Display, Formatter};
+use std::sync::mpsc;
+use std::time::Duration;
+
+#[derive(Debug, into_enum::IntoEnum)]
+pub enum SourceError {
+    LastFM(prost_lastfm::error::Error),
+    Mpd(crate::mpd::MpdError),
+    #[cfg(feature = "mpris")]
+    DBus(dbus::Error),
+}
+
+impl Display for SourceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SourceError::LastFM(err) => write!(f, "SourceError(LastFM) {{ {} }}", err),
+            SourceError::Mpd(err) => write!(f, "SourceError(Mpd) {{ {} }}", err),
+            #[cfg(feature = "mpris")]
+            SourceError::DBus(err) => write!(f, "SourceError(DBus) {{ {} }}", err),
+        }
+    }
+}
+
+impl std::error::Error for SourceError {}
+
+/// Somewhere to read now playing from.
+pub trait TrackSource: Send {
+    /// Names the source in logs.
+    fn name(&self) -> String;
+
+    /// The player state, or `None` if nothing is playing or paused.
+    fn current(&mut self) -> Result<Option<PlayerState>, SourceError>;
+
+    /// Blocks until the state may have changed or `timeout` passes. Sources
+    /// that cannot be notified of changes wait out the whole timeout.
+    fn wait(&mut self, timeout: Duration) -> Result<(), SourceError>;
+}
+
+pub enum SourceEvent {
+    /// The state to report changed. It comes from the first source in the
+    /// list that is playing, or failing that the first one paused.
+    Changed(Option<PlayerState>),
+    /// A source failed. It is skipped until it recovers.
+    Error(String, SourceError),
+    /// [`Interrupter::interrupt`] was called.
+    Interrupted,
+}
+
+struct Update {
+    index: usize,
+    result: Result<Option<PlayerState>, SourceError>,
+}
+
+enum Message {
+    Update(Box<Update>),
+    Interrupt,
+}
+
+/// Wakes whoever waits on [`Sources::recv_timeout`], such as to shut down.
+#[derive(Clone)]
+pub struct Interrupter {
+    sender: mpsc::Sender<Message>,
+}
+
+impl Interrupter {
+    pub fn interrupt(&self) {
+        // Nobody is left to wake if the receiver is gone.
+        let _ = self.sender.send(Message::Interrupt);
+    }
+}
+
+/// Follows a list of sources, each on its own thread, and merges their
+/// states in list order.
+pub struct Sources {
+    names: Vec<String>,
+    sender: mpsc::Sender<Message>,
+    receiver: mpsc::Receiver<Message>,
+    /// The last state of each source, or `None` after an error.
+    states: Vec<Option<PlayerState>>,
+    current: Option<PlayerState>,
+}
+
+impl Sources {
+    /// Starts following `sources`. Sources are checked at least every
+    /// `interval`.
+    pub fn spawn(sources: Vec<Box<dyn TrackSource>>, interval: Duration) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let names = sources
+            .iter()
+            .map(|source| source.name())
+            .collect::<Vec<_>>();
+        for (index, source) in sources.into_iter().enumerate() {
+            let sender = sender.clone();
+            std::thread::spawn(move || follow(index, source, interval, sender));
+        }
+        Self {
+            states: vec![None; names.len()],
+            names,
+            sender,
+            receiver,
+            current: None,
+        }
+    }
+
+    pub fn interrupter(&self) -> Interrupter {
+        Interrupter {
+            sender: self.sender.clone(),
+        }
+    }
+
+    pub fn current(&self) -> Option<&PlayerState> {
+        self.current.as_ref()
+    }
+
+    /// Waits up to `timeout` for the next event.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Option<SourceEvent> {
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            // Checked before waiting so a change caused by an error is
+            // reported on the call after the error.
+            if let Some(state) = self.take_change() {
+                return Some(SourceEvent::Changed(state));
+            }
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            let Update { index, result } = match self.receiver.recv_timeout(remaining).ok()? {
+                Message::Update(update) => *update,
+                Message::Interrupt => return Some(SourceEvent::Interrupted),
+            };
+            match result {
+                Ok(state) => self.states[index] = state,
+                Err(err) => {
+                    self.states[index] = None;
+                    return Some(SourceEvent::Error(self.names[index].clone(), err));
+                }
+            }
+        }
+    }
+
+    fn take_change(&mut self) -> Option<Option<PlayerState>> {
+        let merged = self
+            .states
+            .iter()
+            .flatten()
+            .find(|state| state.status == PlaybackStatus::Playing)
+            .or_else(|| {
+                self.states
+                    .iter()
+                    .flatten()
+                    .find(|state| state.status == PlaybackStatus::Paused)
+            });
+        let changed = match (merged, &self.current) {
+            (Some(merged), Some(current)) => !merged.continues(current),
+            (None, None) => false,
+            _ => true,
+        };
+        if !changed {
+            return None;
+        }
+        self.current = merged.cloned();
+        Some(self.current.clone())
+    }
+}
+
+/// Reports the state of `source` whenever it changes, and errors as they
+/// happen. Stops once the receiver is gone.
+fn follow(
+    index: usize,
+    mut source: Box<dyn TrackSource>,
+    interval: Duration,
+    sender: mpsc::Sender<Message>,
+) {
+    // `None` until the first successful read.
+    let mut last: Option<Option<PlayerState>> = None;
+    loop {
+        let result = source.current();
+        let send = match (&result, &last) {
+            (Ok(Some(state)), Some(Some(last))) => !state.continues(last),
+            (Ok(None), Some(None)) => false,
+            _ => true,
+        };
+        if let Ok(state) = &result {
+            last = Some(state.clone());
+        } else {
+            last = None;
+        }
+        if send
+            && sender
+                .send(Message::Update(Box::new(Update { index, result })))
+                .is_err()
+        {
+            return;
+        }
+        if let Err(err) = source.wait(interval) {
+            last = None;
+            if sender
+                .send(Message::Update(Box::new(Update {
+                    index,
+                    result: Err(err),
+                })))
+                .is_err()
+            {
+                return;
+            }
+            std::thread::sleep(interval);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+/// Placeholders a template may use.
+pub const FIELDS: &[&str] = &["title", "artist", "album", "playcount", "user", "url"];
+
+/// Values for a template's placeholders. Missing fields are left out.
+pub type TemplateValues = HashMap<&'static str, String>;
+
+#[derive(Debug)]
+pub struct TemplateError {
+    pub template: String,
+    /// Byte offset of the problem in the template.
+    pub position: usize,
+    pub message: String,
+}
+
+impl Display for TemplateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid template \"{}\" at {}: {}",
+            self.template, self.position, self.message
+        )
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Field(&'static str),
+    /// Rendered only if every field directly inside it has a value.
+    Section(Vec<Part>),
+}
+
+/// A format string for presence text.
+///
+/// `{field}` is replaced with the value of a field from [`FIELDS`], or nothing
+/// if the field is missing. `[...]` is a section that is left out entirely
+/// unless every field directly inside it has a value, so `{artist}[ - {album}]`
+/// drops the separator when there is no album. Sections may be nested. `{{`,
+/// `}}`, `[[` and `]]` produce literal brackets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    source: String,
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        let error = |position: usize, message: String| TemplateError {
+            template: source.to_string(),
+            position,
+            message,
+        };
+        // Open sections, with the offset each one starts at.
+        let mut stack: Vec<(usize, Vec<Part>)> = vec![(0, Vec::new())];
+        let mut text = String::new();
+        let mut chars = source.char_indices().peekable();
+        while let Some((position, c)) = chars.next() {
+            match c {
+                '{' | '}' | '[' | ']' if chars.peek().map(|(_, next)| *next) == Some(c) => {
+                    chars.next();
+                    text.push(c);
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some((_, '}')) => break,
+                            Some((_, c)) => name.push(c),
+                            None => return Err(error(position, String::from("unclosed {"))),
+                        }
+                    }
+                    let Some(field) = FIELDS.iter().find(|field| **field == name) else {
+                        return Err(error(
+                            position,
+                            format!(
+                                "unknown field {{{}}}, expected one of {}",
+                                name,
+                                FIELDS.join(", ")
+                            ),
+                        ));
+                    };
+                    let parts = &mut stack.last_mut().unwrap().1;
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Field(field));
+                }
+                '}' => return Err(error(position, String::from("unmatched }"))),
+                '[' => {
+                    if !text.is_empty() {
+                        let parts = &mut stack.last_mut().unwrap().1;
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    stack.push((position, Vec::new()));
+                }
+                ']' => {
+                    if stack.len() == 1 {
+                        return Err(error(position, String::from("unmatched ]")));
+                    }
+                    let (_, mut parts) = stack.pop().unwrap();
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    stack.last_mut().unwrap().1.push(Part::Section(parts));
+                }
+                c => text.push(c),
+            }
+        }
+        if stack.len() > 1 {
+            return Err(error(stack.pop().unwrap().0, String::from("unclosed [")));
+        }
+        let mut parts = stack.pop().unwrap().1;
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Self {
+            source: source.to_string(),
+            parts,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether the template refers to `field` anywhere.
+    pub fn uses(&self, field: &str) -> bool {
+        fn uses(parts: &[Part], field: &str) -> bool {
+            parts.iter().any(|part| match part {
+                Part::Text(_) => false,
+                Part::Field(name) => *name == field,
+                Part::Section(parts) => uses(parts, field),
+            })
+        }
+        uses(&self.parts, field)
+    }
+
+    pub fn render(&self, values: &TemplateValues) -> String {
+        let mut out = String::new();
+        render(&self.parts, values, &mut out);
+        out
+    }
+}
+
+impl std::str::FromStr for Template {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Template::parse(s)
+    }
+}
+
+fn render(parts: &[Part], values: &TemplateValues, out: &mut String) {
+    for part in parts {
+        match part {
+            Part::Text(text) => out.push_str(text),
+            Part::Field(name) => {
+                if let Some(value) = values.get(name) {
+                    out.push_str(value);
+                }
+            }
+            Part::Section(parts) => {
+                let complete = parts.iter().all(|part| match part {
+                    Part::Field(name) => values.get(name).is_some_and(|value| !value.is_empty()),
+                    _ => true,
+                });
+                if complete {
+                    render(parts, values, out);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use crate::lastfm::InitToken;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Starts every token file written by [`TokenStore`]. Files without it are
+/// from before, when the token was only base64 encoded.
+const MAGIC: &str = "eclect-token:1:";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, into_enum::IntoEnum)]
+pub enum TokenError {
+    Io(std::io::Error),
+    /// Other users can read the file, so the token may have leaked.
+    #[into_enum(skip)]
+    Permissions(String),
+    /// The file is not a token, or is damaged.
+    #[into_enum(skip)]
+    Format(String),
+    /// The passphrase is missing, or does not decrypt the token.
+    #[into_enum(skip)]
+    Passphrase(String),
+}
+
+impl Display for TokenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenError::Io(err) => write!(f, "TokenError(Io) {{ {} }}", err),
+            TokenError::Permissions(msg) => write!(f, "TokenError(Permissions) {{ {} }}", msg),
+            TokenError::Format(msg) => write!(f, "TokenError(Format) {{ {} }}", msg),
+            TokenError::Passphrase(msg) => write!(f, "TokenError(Passphrase) {{ {} }}", msg),
+        }
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+/// Where the passphrase for encrypting the token comes from.
+#[derive(Clone, Debug)]
+pub enum PassphraseSource {
+    /// The contents of a file, without the trailing newline.
+    File(PathBuf),
+    /// What a command prints, such as a password manager. Run through the
+    /// shell.
+    Command(String),
+}
+
+impl PassphraseSource {
+    pub fn read(&self) -> Result<String, TokenError> {
+        let passphrase = match self {
+            PassphraseSource::File(path) => std::fs::read_to_string(path).map_err(|err| {
+                TokenError::Passphrase(format!("error reading {}: {}", path.display(), err))
+            })?,
+            PassphraseSource::Command(command) => crate::secret::command_output(command)
+                .map_err(|err| TokenError::Passphrase(format!("passphrase {}", err)))?,
+        };
+        let passphrase = passphrase.trim_end_matches(['\r', '\n']);
+        if passphrase.is_empty() {
+            return Err(TokenError::Passphrase(String::from("passphrase is empty")));
+        }
+        Ok(passphrase.to_string())
+    }
+}
+
+/// Keeps the Last.fm token on disk, readable only by the current user, and
+/// encrypted if a passphrase is given.
+///
+/// Files are replaced whole through a rename, so an interrupted write leaves
+/// the old token in place.
+pub struct TokenStore {
+    path: PathBuf,
+    passphrase: Option<PassphraseSource>,
+    /// Read once, so a password manager is not asked on every write.
+    cached: OnceLock<String>,
+}
+
+impl TokenStore {
+    pub fn new(path: impl Into<PathBuf>, passphrase: Option<PassphraseSource>) -> Self {
+        Self {
+            path: path.into(),
+            passphrase,
+            cached: OnceLock::new(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    fn passphrase(&self) -> Result<Option<&str>, TokenError> {
+        let Some(source) = &self.passphrase else {
+            return Ok(None);
+        };
+        if self.cached.get().is_none() {
+            let _ = self.cached.set(source.read()?);
+        }
+        Ok(self.cached.get().map(String::as_str))
+    }
+
+    /// Reads the token. Files from before are rewritten in the current
+    /// format, as are unencrypted files once a passphrase is given.
+    pub fn read(&self) -> Result<InitToken, TokenError> {
+        // Checked before the token is read. Files from before were written
+        // with the default permissions, so those are tightened instead.
+        if let Some(mode) = self.loose_mode()? {
+            if !self.is_legacy()? {
+                return Err(TokenError::Permissions(format!(
+                    "{} can be read by other users (mode {:o}); run chmod 600 on it, or log in again",
+                    self.path.display(),
+                    mode
+                )));
+            }
+            self.restrict_permissions()?;
+            println!(
+                "{} could be read by other users (mode {:o}), and is now only readable by you. Log in again if others may have copied it.",
+                self.path.display(),
+                mode
+            );
+        }
+        let raw = std::fs::read_to_string(&self.path)?;
+        let Some(contents) = raw.trim_end().strip_prefix(MAGIC) else {
+            let token = self.decode_legacy(raw.trim_end())?;
+            self.write(&token)?;
+            println!("Moved {} to the current token format", self.path.display());
+            return Ok(token);
+        };
+        let (token, encrypted) = match contents.split_once(':') {
+            Some(("plain", data)) => (self.decode_json(&decode_base64(data)?)?, false),
+            Some(("encrypted", data)) => (self.decrypt(data)?, true),
+            _ => return Err(self.format_error("unknown token kind")),
+        };
+        if !encrypted && self.passphrase.is_some() {
+            self.write(&token)?;
+            println!("Encrypted {}", self.path.display());
+        }
+        Ok(token)
+    }
+
+    pub fn write(&self, token: &InitToken) -> Result<(), TokenError> {
+        let json = serde_json::to_vec(token)
+            .map_err(|err| self.format_error(&format!("failed to serialize token: {}", err)))?;
+        let contents = match self.passphrase()? {
+            Some(passphrase) => format!("{}encrypted:{}", MAGIC, encrypt(passphrase, &json)?),
+            None => format!(
+                "{}plain:{}",
+                MAGIC,
+                base64::engine::general_purpose::STANDARD.encode(json)
+            ),
+        };
+        let temp_path = self.path.with_extension("tmp");
+        // A leftover file would keep its permissions when opened.
+        match std::fs::remove_file(&temp_path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&temp_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+        std::fs::rename(&temp_path, &self.path)?;
+        Ok(())
+    }
+
+    /// The file's mode, if other users have any access to it.
+    #[cfg(unix)]
+    fn loose_mode(&self) -> Result<Option<u32>, TokenError> {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&self.path)?.permissions().mode() & 0o777;
+        Ok((mode & 0o077 != 0).then_some(mode))
+    }
+
+    #[cfg(not(unix))]
+    fn loose_mode(&self) -> Result<Option<u32>, TokenError> {
+        Ok(None)
+    }
+
+    #[cfg(unix)]
+    fn restrict_permissions(&self) -> Result<(), TokenError> {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&self.path, std::fs::Permissions::from_mode(0o600))?;
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn restrict_permissions(&self) -> Result<(), TokenError> {
+        Ok(())
+    }
+
+    /// Whether the file is from before, going by its start alone.
+    fn is_legacy(&self) -> Result<bool, TokenError> {
+        use std::io::Read;
+        let mut start = Vec::new();
+        std::fs::File::open(&self.path)?
+            .take(MAGIC.len() as u64)
+            .read_to_end(&mut start)?;
+        Ok(start != MAGIC.as_bytes())
+    }
+
+    fn decode_legacy(&self, raw: &str) -> Result<InitToken, TokenError> {
+        self.decode_json(&decode_base64(raw).map_err(|_| self.format_error("not a token file"))?)
+    }
+
+    fn decode_json(&self, json: &[u8]) -> Result<InitToken, TokenError> {
+        serde_json::from_slice(json).map_err(|err| self.format_error(&err.to_string()))
+    }
+
+    fn decrypt(&self, data: &str) -> Result<InitToken, TokenError> {
+        let Some(passphrase) = self.passphrase()? else {
+            return Err(TokenError::Passphrase(format!(
+                "{} is encrypted, and no passphrase was given",
+                self.path.display()
+            )));
+        };
+        let parts = data
+            .split(':')
+            .map(decode_base64)
+            .collect::<Result<Vec<_>, _>>()?;
+        let [salt, nonce, ciphertext] = parts.as_slice() else {
+            return Err(self.format_error("damaged encrypted token"));
+        };
+        if nonce.len() != NONCE_LEN {
+            return Err(self.format_error("damaged encrypted token"));
+        }
+        let json = cipher(passphrase, salt)?
+            .decrypt(Nonce::from_slice(nonce), ciphertext.as_slice())
+            .map_err(|_| {
+                TokenError::Passphrase(format!(
+                    "wrong passphrase for {}, or the file is damaged",
+                    self.path.display()
+                ))
+            })?;
+        self.decode_json(&json)
+    }
+
+    fn format_error(&self, msg: &str) -> TokenError {
+        TokenError::Format(format!("error reading {}: {}", self.path.display(), msg))
+    }
+}
+
+fn decode_base64(data: &str) -> Result<Vec<u8>, TokenError> {
+    base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|err| TokenError::Format(format!("error decoding token: {}", err)))
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<ChaCha20Poly1305, TokenError> {
+    let mut key = Key::default();
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|err| TokenError::Passphrase(format!("error deriving key: {}", err)))?;
+    Ok(ChaCha20Poly1305::new(&key))
+}
+
+/// Encrypts with a key derived from `passphrase` and a new salt. Returns the
+/// salt, nonce and ciphertext in base64, separated by colons.
+fn encrypt(passphrase: &str, data: &[u8]) -> Result<String, TokenError> {
+    let mut salt = [0; SALT_LEN];
+    let mut nonce = [0; NONCE_LEN];
+    getrandom::getrandom(&mut salt)
+        .and_then(|_| getrandom::getrandom(&mut nonce))
+        .map_err(|err| TokenError::Io(std::io::Error::other(err.to_string())))?;
+    let ciphertext = cipher(passphrase, &salt)?
+        .encrypt(Nonce::from_slice(&nonce), data)
+        .map_err(|_| TokenError::Format(String::from("error encrypting token")))?;
+    let base64 = base64::engine::general_purpose::STANDARD;
+    Ok(format!(
+        "{}:{}:{}",
+        base64.encode(salt),
+        base64.encode(nonce),
+        base64.encode(ciphertext)
+    ))
+}
@@ -0,0 +1,153 @@
+use eclect::lastfm::{InitToken, mobile_session, wait_for_session};
+use eclect::token::TokenStore;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Answers requests with `bodies` in order, one connection each, and reports
+/// the request line of each. Returns the endpoint.
+fn serve(bodies: Vec<&'static str>) -> (String, mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}/2.0/", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for body in bodies {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut content_length = 0;
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                if let Some((name, value)) = line.split_once(':')
+                    && name.eq_ignore_ascii_case("content-length")
+                {
+                    content_length = value.trim().parse().unwrap();
+                }
+                line.clear();
+            }
+            reader.read_exact(&mut vec![0; content_length]).unwrap();
+            let status = if body.contains("\"error\"") {
+                "403 Forbidden"
+            } else {
+                "200 OK"
+            };
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+            sender.send(request_line).unwrap();
+        }
+    });
+    (endpoint, receiver)
+}
+
+/// A store in a temporary directory, removed when the guard is dropped.
+fn token_store() -> (tempfile::TempDir, TokenStore) {
+    let dir = tempfile::tempdir().unwrap();
+    let tokens = TokenStore::new(dir.path().join("token.bin"), None);
+    (dir, tokens)
+}
+
+#[test]
+fn auth_waits_for_approval() {
+    let (endpoint, requests) = serve(vec![
+        r#"{"message":"This token has not been authorized","error":14}"#,
+        r#"{"message":"This token has not been authorized","error":14}"#,
+        r#"{"message":"This token has expired","error":15}"#,
+        r#"{"token":"second"}"#,
+        r#"{"session":{"name":"someone","key":"session-key","subscriber":0}}"#,
+    ]);
+    let agent = prost_lastfm::AuthServiceAgent::new(
+        reqwest::blocking::Client::new(),
+        String::from("key"),
+        String::from("secret"),
+        endpoint,
+    );
+    let (_dir, tokens) = token_store();
+    let mut prompts = Vec::new();
+    let session = wait_for_session(
+        &agent,
+        &tokens,
+        String::from("first"),
+        Duration::from_millis(10),
+        |token| prompts.push(token.to_string()),
+    );
+    assert_eq!(session.ok().as_deref(), Some("session-key"));
+    // The expired token was replaced, and the user sent to the new one.
+    assert_eq!(prompts, ["first", "second"]);
+    let methods = requests
+        .iter()
+        .take(5)
+        .map(|line| {
+            let method = line.split("method=").nth(1).unwrap();
+            method[..method.find('&').unwrap()].to_string()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        methods,
+        [
+            "auth.getSession",
+            "auth.getSession",
+            "auth.getSession",
+            "auth.getToken",
+            "auth.getSession"
+        ]
+    );
+    assert!(matches!(
+        tokens.read(),
+        Ok(InitToken::Session(key)) if key == "session-key"
+    ));
+}
+
+#[test]
+fn auth_gives_up_on_bad_key() {
+    let (endpoint, _requests) = serve(vec![
+        r#"{"message":"Invalid API key - You must be granted a valid key by last.fm","error":10}"#,
+    ]);
+    let agent = prost_lastfm::AuthServiceAgent::new(
+        reqwest::blocking::Client::new(),
+        String::from("key"),
+        String::from("secret"),
+        endpoint,
+    );
+    let (_dir, tokens) = token_store();
+    let result = wait_for_session(
+        &agent,
+        &tokens,
+        String::from("first"),
+        Duration::from_millis(10),
+        |_| {},
+    );
+    assert!(matches!(
+        result,
+        Err(eclect::lastfm::InitError::RequestError(err))
+            if err.code() == Some(prost_lastfm::error::ErrorCode::InvalidApiKey)
+    ));
+    assert!(!tokens.exists());
+}
+
+#[test]
+fn auth_mobile_session() {
+    let (endpoint, requests) = serve(vec![
+        r#"{"session":{"name":"someone","key":"mobile-key","subscriber":0}}"#,
+    ]);
+    let agent = prost_lastfm::AuthServiceAgent::new(
+        reqwest::blocking::Client::new(),
+        String::from("key"),
+        String::from("secret"),
+        endpoint,
+    );
+    let (_dir, tokens) = token_store();
+    let session = mobile_session(&agent, &tokens, "someone", "hunter2");
+    assert_eq!(session.ok().as_deref(), Some("mobile-key"));
+    assert!(requests.recv().unwrap().starts_with("POST /2.0/ "));
+    assert!(matches!(
+        tokens.read(),
+        Ok(InitToken::Session(key)) if key == "mobile-key"
+    ));
+}
@@ -0,0 +1,77 @@
+use eclect::backoff::{Backoff, RATE_LIMIT_DELAY, Severity};
+use prost_lastfm::error::{Error, ErrorCode, LastFMError};
+use std::time::Duration;
+
+const BASE: Duration = Duration::from_secs(15);
+const MAX: Duration = Duration::from_secs(600);
+
+/// Checks a delay is `nominal` plus at most half again, within `MAX`.
+fn assert_jittered(delay: Option<Duration>, nominal: Duration) {
+    let delay = delay.expect("failure not retried");
+    assert!(delay >= nominal.min(MAX), "{:?} < {:?}", delay, nominal);
+    assert!(
+        delay <= (nominal + nominal / 2).min(MAX),
+        "{:?} > {:?}",
+        delay,
+        nominal
+    );
+}
+
+#[test]
+fn backoff_grows_and_caps() {
+    let mut backoff = Backoff::new(BASE, MAX);
+    for failure in 0..8 {
+        let nominal = BASE * (1 << failure);
+        assert_jittered(backoff.failure(Severity::Transient), nominal);
+    }
+    assert_eq!(backoff.failures(), 8);
+    for _ in 0..100 {
+        assert_jittered(backoff.failure(Severity::Transient), MAX);
+    }
+
+    backoff.reset();
+    assert_jittered(backoff.failure(Severity::Transient), BASE);
+}
+
+#[test]
+fn backoff_rate_limit() {
+    let mut backoff = Backoff::new(BASE, MAX);
+    assert_jittered(backoff.failure(Severity::RateLimited), RATE_LIMIT_DELAY);
+    assert_jittered(backoff.failure(Severity::RateLimited), RATE_LIMIT_DELAY * 2);
+    // Request errors poll at the usual interval and do not add to the count.
+    assert_eq!(backoff.failure(Severity::Request), Some(BASE));
+    assert_eq!(backoff.failures(), 2);
+    assert_eq!(backoff.failure(Severity::Reauth), None);
+    assert_eq!(backoff.failure(Severity::Fatal), None);
+}
+
+#[test]
+fn backoff_jitter_varies() {
+    let delays = (0..20)
+        .map(|_| Backoff::new(BASE, MAX).failure(Severity::Transient))
+        .collect::<std::collections::HashSet<_>>();
+    assert!(delays.len() > 1);
+}
+
+#[test]
+fn severity_of_codes() {
+    let severity = |code| {
+        Severity::of(&Error::LastFM(LastFMError {
+            message: String::new(),
+            error: code,
+            retry_after: None,
+        }))
+    };
+    assert_eq!(
+        severity(ErrorCode::RateLimitExceeded),
+        Severity::RateLimited
+    );
+    assert_eq!(severity(ErrorCode::ServiceOffline), Severity::Transient);
+    assert_eq!(severity(ErrorCode::InternalError), Severity::Transient);
+    assert_eq!(severity(ErrorCode::InvalidParameters), Severity::Request);
+    assert_eq!(severity(ErrorCode::InvalidSessionKey), Severity::Reauth);
+    assert_eq!(severity(ErrorCode::InvalidApiKey), Severity::Fatal);
+    assert_eq!(severity(ErrorCode::SuspendedApiKey), Severity::Fatal);
+    assert!(severity(ErrorCode::InvalidSessionKey).is_fatal());
+    assert!(!severity(ErrorCode::RateLimitExceeded).is_fatal());
+}
@@ -0,0 +1,66 @@
+use eclect::callback::{ACCEPT_TIMEOUT, CallbackListener};
+use std::io::Write;
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+fn get(url: String) -> (u16, String) {
+    let response = reqwest::blocking::get(url).unwrap();
+    (response.status().as_u16(), response.text().unwrap())
+}
+
+#[test]
+fn callback_catches_token() {
+    let listener = CallbackListener::bind(0).unwrap();
+    let url = listener.url().unwrap();
+    assert!(url.starts_with("http://127.0.0.1:"), "{}", url);
+    let browser = std::thread::spawn(move || {
+        // Browsers ask for other things too, which are turned away.
+        let favicon = get(format!("{}favicon.ico", url));
+        let callback = get(format!("{}?token=abc%20def", url));
+        (favicon, callback)
+    });
+
+    let callback = listener.accept(ACCEPT_TIMEOUT).unwrap();
+    assert_eq!(callback.token, "abc def");
+    callback.finish(Ok(())).unwrap();
+    let ((favicon_status, _), (status, page)) = browser.join().unwrap();
+    assert_eq!(favicon_status, 404);
+    assert_eq!(status, 200);
+    assert!(page.contains("authorized"), "{}", page);
+}
+
+#[test]
+fn callback_reports_failure() {
+    let listener = CallbackListener::bind(0).unwrap();
+    let url = listener.url().unwrap();
+    let browser = std::thread::spawn(move || get(format!("{}?token=abc", url)));
+
+    let callback = listener.accept(ACCEPT_TIMEOUT).unwrap();
+    callback.finish(Err("token <expired>")).unwrap();
+    let (status, page) = browser.join().unwrap();
+    assert_eq!(status, 500);
+    assert!(page.contains("token &lt;expired&gt;"), "{}", page);
+}
+
+#[test]
+fn callback_times_out() {
+    let listener = CallbackListener::bind(0).unwrap();
+    let started = Instant::now();
+    let err = listener.accept(Duration::from_millis(300)).err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+    assert!(started.elapsed() >= Duration::from_millis(300));
+
+    // A browser that connects but never finishes its request does not hold
+    // the wait open either.
+    let address = listener.url().unwrap();
+    let address = address
+        .trim_start_matches("http://")
+        .trim_end_matches('/')
+        .to_string();
+    let mut stalled = TcpStream::connect(address).unwrap();
+    stalled.write_all(b"GET /?token=abc").unwrap();
+    let started = Instant::now();
+    let err = listener.accept(Duration::from_millis(300)).err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
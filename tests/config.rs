@@ -0,0 +1,67 @@
+use clap::Parser;
+use eclect::config::Config;
+
+#[derive(clap::Args, serde::Deserialize, Debug)]
+struct Flags {
+    #[clap(long, default_value = "data")]
+    workdir: String,
+    #[clap(long, default_value_t = 15)]
+    query_interval: u64,
+}
+
+#[derive(clap::Parser, Debug)]
+#[clap(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    #[clap(flatten)]
+    run: Config<Flags>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    Status(Config<Flags>),
+}
+
+#[test]
+fn config_file_in_subcommand() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("eclect.toml");
+    std::fs::write(&path, "workdir = \"elsewhere\"\nquery_interval = 30\n").unwrap();
+    let path = path.to_str().unwrap();
+
+    let cli = Cli::try_parse_from(["eclect", "status", "-c", path]).unwrap();
+    let Some(Command::Status(config)) = cli.command else {
+        panic!("expected status, got {:?}", cli.command);
+    };
+    assert_eq!(config.config_path.as_deref(), Some(path));
+    assert_eq!(config.inner.workdir, "elsewhere");
+    assert_eq!(config.inner.query_interval, 30);
+
+    // Without a subcommand the flags belong to the default command.
+    let cli = Cli::try_parse_from(["eclect", "-c", path]).unwrap();
+    assert!(cli.command.is_none());
+    assert_eq!(cli.run.inner.query_interval, 30);
+
+    let cli = Cli::try_parse_from(["eclect", "status", "--workdir", "here"]).unwrap();
+    let Some(Command::Status(config)) = cli.command else {
+        panic!("expected status, got {:?}", cli.command);
+    };
+    assert_eq!(
+        (config.inner.workdir.as_str(), config.inner.query_interval),
+        ("here", 15)
+    );
+}
+
+#[test]
+fn config_flag_for_update() {
+    use clap::CommandFactory;
+    let has_flag = |command: clap::Command| {
+        command
+            .get_arguments()
+            .any(|arg| arg.get_id() == eclect::config::CONFIG_FLAG)
+    };
+    assert!(has_flag(Config::<Flags>::command()));
+    assert!(has_flag(Config::<Flags>::command_for_update()));
+    assert!(has_flag(Cli::command_for_update()));
+}
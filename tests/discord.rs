@@ -0,0 +1,64 @@
+use eclect::discord::{PlayClock, PlayTimes};
+use eclect::player::{PlaybackStatus, PlayerState, build_track};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const T0: u64 = 1_700_000_000;
+
+fn at(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(T0 + secs)
+}
+
+fn state(title: &str, length: Option<u64>, position: Option<u64>) -> PlayerState {
+    PlayerState {
+        player: String::from("test"),
+        status: PlaybackStatus::Playing,
+        track: build_track(title, "Artist", None, None),
+        length: length.map(Duration::from_secs),
+        position: position.map(Duration::from_secs),
+    }
+}
+
+fn times(start: u64, end: Option<u64>) -> PlayTimes {
+    PlayTimes {
+        start: (T0 + start) as i64,
+        end: end.map(|end| (T0 + end) as i64),
+    }
+}
+
+#[test]
+fn clock_with_position() {
+    let mut clock = PlayClock::default();
+    let playing = state("Song", Some(180), Some(30));
+    assert_eq!(clock.times(&playing, at(100)), times(70, Some(250)));
+    // The same state seen later keeps its times.
+    assert_eq!(clock.times(&playing, at(160)), times(70, Some(250)));
+    // Repeating the song starts the clock over.
+    let repeated = state("Song", Some(180), Some(0));
+    assert_eq!(clock.times(&repeated, at(251)), times(251, Some(431)));
+}
+
+#[test]
+fn clock_without_position() {
+    let mut clock = PlayClock::default();
+    let playing = state("Song", Some(180), None);
+    assert_eq!(clock.times(&playing, at(0)), times(0, Some(180)));
+    assert_eq!(clock.times(&playing, at(90)), times(0, Some(180)));
+    // Still playing after it should have ended, so it repeated.
+    assert_eq!(clock.times(&playing, at(200)), times(180, Some(360)));
+    assert_eq!(clock.times(&playing, at(1000)), times(900, Some(1080)));
+
+    let next = state("Next", Some(100), None);
+    assert_eq!(clock.times(&next, at(1010)), times(1010, Some(1110)));
+}
+
+#[test]
+fn clock_unknown_length() {
+    let mut clock = PlayClock::default();
+    let playing = state("Song", None, None);
+    assert_eq!(clock.times(&playing, at(0)), times(0, None));
+    assert_eq!(clock.times(&playing, at(10_000)), times(0, None));
+
+    // A length found later keeps the start.
+    let found = state("Song", Some(20_000), None);
+    assert_eq!(clock.times(&found, at(10_010)), times(0, Some(20_000)));
+}
@@ -0,0 +1,226 @@
+#![cfg(unix)]
+
+use discord_mock_server::MockDiscord;
+use discord_rich_presence::DiscordIpcClient;
+use eclect::backoff::Backoff;
+use eclect::discord::{
+    DiscordConnection, PlayTimes, Presence, PresenceTemplates, PresenceUpdate, track_values,
+};
+use eclect::player::build_track;
+use eclect::template::Template;
+use lastfm_mock_server::{MockServer, MockTrack};
+use serde_json::json;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// The client finds the socket through the environment, which is shared by
+/// every test in this file.
+static RUNTIME_DIR: Mutex<()> = Mutex::new(());
+
+fn start() -> (MockDiscord, MutexGuard<'static, ()>) {
+    let guard = RUNTIME_DIR.lock().unwrap_or_else(|err| err.into_inner());
+    let discord = MockDiscord::start();
+    // SAFETY: only tests holding RUNTIME_DIR read or write the variable.
+    unsafe { std::env::set_var("XDG_RUNTIME_DIR", discord.runtime_dir()) };
+    (discord, guard)
+}
+
+fn connect() -> DiscordConnection<DiscordIpcClient> {
+    let mut connection = DiscordConnection::new(
+        DiscordIpcClient::new("1234").unwrap(),
+        Backoff::new(Duration::from_secs(5), Duration::from_secs(120)),
+    );
+    assert!(connection.maintain(Instant::now()).unwrap());
+    connection
+}
+
+fn templates() -> PresenceTemplates {
+    PresenceTemplates {
+        details: Template::parse("{title}").unwrap(),
+        state: Template::parse("by {artist}").unwrap(),
+        large_text: Template::parse("{album}").unwrap(),
+        small_image: None,
+        small_text: Template::parse("").unwrap(),
+        buttons: vec![
+            (
+                Template::parse("Listen").unwrap(),
+                Template::parse("{url}").unwrap(),
+            ),
+            // Left out, since the label is empty.
+            (
+                Template::parse("").unwrap(),
+                Template::parse("{url}").unwrap(),
+            ),
+        ],
+    }
+}
+
+#[test]
+fn ipc_presence() {
+    let (discord, _guard) = start();
+    let mut connection = connect();
+    let mut presence = Presence::new(templates(), Duration::from_secs(300));
+    let mut track = build_track("Roygbiv", "Boards of Canada", None, None);
+    track.url = String::from("https://www.last.fm/music/Boards+of+Canada/_/Roygbiv");
+    let times = PlayTimes {
+        start: 1_700_000_000,
+        end: Some(1_700_000_151),
+    };
+    let update = presence.update(
+        connection.client().unwrap(),
+        Some(&track),
+        &track_values(&track, None, None),
+        Some(times),
+        Instant::now(),
+    );
+    assert_eq!(
+        update.unwrap(),
+        PresenceUpdate::Set(String::from("Boards of Canada - Roygbiv"))
+    );
+    let activity = discord.wait_for_activities(1).remove(0).unwrap();
+    assert_eq!(activity["type"], 2);
+    assert_eq!(activity["details"], "Roygbiv");
+    assert_eq!(activity["state"], "by Boards of Canada");
+    assert_eq!(
+        activity["timestamps"],
+        json!({"start": 1_700_000_000, "end": 1_700_000_151})
+    );
+    assert_eq!(
+        activity["buttons"],
+        json!([{"label": "Listen", "url": track.url}])
+    );
+
+    let update = presence.update(
+        connection.client().unwrap(),
+        None,
+        &Default::default(),
+        None,
+        Instant::now(),
+    );
+    assert_eq!(update.unwrap(), PresenceUpdate::Cleared);
+    assert_eq!(discord.wait_for_activities(2)[1], None);
+    assert_eq!(discord.client_ids(), ["1234"]);
+}
+
+#[test]
+fn ipc_disconnect() {
+    let (discord, _guard) = start();
+    let mut connection = connect();
+    let mut presence = Presence::new(templates(), Duration::from_secs(300));
+    let first = build_track("First", "Artist", None, None);
+    let second = build_track("Second", "Artist", None, None);
+    let mut show = |connection: &mut DiscordConnection<_>, track| {
+        presence.update(
+            connection.client().unwrap(),
+            Some(track),
+            &track_values(track, None, None),
+            None,
+            Instant::now(),
+        )
+    };
+    show(&mut connection, &first).unwrap();
+    discord.wait_for_activities(1);
+
+    discord.disconnect();
+    assert!(show(&mut connection, &second).is_err());
+    connection.disconnect();
+    assert!(connection.maintain(Instant::now()).unwrap());
+    show(&mut connection, &second).unwrap();
+    let activities = discord.wait_for_activities(2);
+    assert_eq!(activities[1].as_ref().unwrap()["details"], "Second");
+    assert_eq!(discord.client_ids().len(), 2);
+}
+
+#[test]
+fn ipc_refused() {
+    let (discord, _guard) = start();
+    discord.refuse_connections(true);
+    let mut connection = DiscordConnection::new(
+        DiscordIpcClient::new("1234").unwrap(),
+        Backoff::new(Duration::from_secs(5), Duration::from_secs(120)),
+    );
+    let now = Instant::now();
+    assert!(connection.maintain(now).is_err());
+    assert!(connection.until_retry(now) > Some(Duration::ZERO));
+
+    discord.refuse_connections(false);
+    assert!(connection.maintain(now + Duration::from_secs(120)).unwrap());
+    assert_eq!(discord.client_ids(), ["1234"]);
+}
+
+/// Kills the program if the test fails before stopping it.
+struct Running(Child);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+#[test]
+fn ipc_run() {
+    let (discord, _guard) = start();
+    let lastfm = MockServer::start("key", "secret");
+    lastfm.set_password("hunter2");
+    lastfm.push_recent_tracks(vec![MockTrack::playing(
+        "Roygbiv",
+        "Boards of Canada",
+        "Music Has the Right to Children",
+    )]);
+    let workdir = discord.runtime_dir().join("eclect");
+    std::fs::create_dir_all(&workdir).unwrap();
+    let password_file = workdir.join("password");
+    std::fs::write(&password_file, "hunter2").unwrap();
+    let eclect = |args: &[&str]| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_eclect"));
+        command
+            .args(args)
+            .args(["--workdir", workdir.to_str().unwrap()])
+            .args(["--endpoint", &lastfm.endpoint()])
+            .args(["--lastfm-api-key", "key", "--lastfm-secret", "secret"])
+            .env("XDG_RUNTIME_DIR", discord.runtime_dir());
+        command
+    };
+    let login = [
+        "--username",
+        "someone",
+        "--password-file",
+        password_file.to_str().unwrap(),
+    ];
+    let auth = eclect(&["auth"]).args(login).output().unwrap();
+    assert!(auth.status.success(), "{:?}", auth);
+
+    // With a session saved, the password file is not read again.
+    std::fs::remove_file(&password_file).unwrap();
+    let mut run = Running(
+        eclect(&["run", "--discord-app-id", "1234", "--query-interval", "1"])
+            .args(login)
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap(),
+    );
+    // The presence is cleared until Last.fm answers.
+    let activities = discord.wait_for(|activities| activities.last().is_some_and(Option::is_some));
+    let activity = activities.last().unwrap().as_ref().unwrap();
+    assert_eq!(activity["details"], "Roygbiv");
+    assert_eq!(activity["type"], 2);
+    assert_eq!(
+        activity["buttons"],
+        json!([{
+            "label": "View on last.fm",
+            "url": "https://www.last.fm/music/Boards+of+Canada/_/Roygbiv",
+        }])
+    );
+
+    // Exiting clears the presence.
+    let killed = Command::new("kill")
+        .arg(run.0.id().to_string())
+        .status()
+        .unwrap();
+    assert!(killed.success());
+    assert!(run.0.wait().unwrap().success());
+    assert_eq!(discord.activities().last(), Some(&None));
+    assert_eq!(discord.client_ids(), ["1234"]);
+}
@@ -0,0 +1,112 @@
+use eclect::lastfm::{self, InitError};
+use eclect::token::TokenStore;
+use lastfm_mock_server::{MockServer, MockTrack};
+use prost_lastfm::error::ErrorCode;
+use std::process::Command;
+
+#[test]
+fn lastfm_session_lifecycle() {
+    let server = MockServer::start("key", "secret");
+    let dir = tempfile::tempdir().unwrap();
+    let tokens = TokenStore::new(dir.path().join("token.bin"), None);
+    let client = reqwest::blocking::Client::new();
+    let activate =
+        || lastfm::activate_session(client.clone(), &server.endpoint(), "key", "secret", &tokens);
+
+    let Err(InitError::NeedAuth(auth_token)) = activate() else {
+        panic!("expected authorization to be needed");
+    };
+    // Not approved yet, so the same token is still waiting.
+    let Err(InitError::NeedAuth(waiting)) = activate() else {
+        panic!("expected authorization to be needed");
+    };
+    assert_eq!(waiting, auth_token);
+
+    server.approve(&auth_token);
+    let Ok((agent, session_key, user)) = activate() else {
+        panic!("expected a session");
+    };
+    assert_eq!(user.name, "someone");
+
+    server.push_recent_tracks(vec![
+        MockTrack::playing(
+            "Roygbiv",
+            "Boards of Canada",
+            "Music Has the Right to Children",
+        ),
+        MockTrack::played(
+            "Aquarius",
+            "Boards of Canada",
+            "Music Has the Right to Children",
+        ),
+    ]);
+    let track = lastfm::now_playing(&agent, Some(&session_key)).unwrap();
+    assert_eq!(track.map(|track| track.name).as_deref(), Some("Roygbiv"));
+
+    server.revoke_session(&session_key);
+    let err = lastfm::now_playing(&agent, Some(&session_key)).unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::InvalidSessionKey));
+}
+
+#[test]
+fn lastfm_wrong_secret() {
+    let server = MockServer::start("key", "secret");
+    let dir = tempfile::tempdir().unwrap();
+    let tokens = TokenStore::new(dir.path().join("token.bin"), None);
+    let result = lastfm::activate_session(
+        reqwest::blocking::Client::new(),
+        &server.endpoint(),
+        "key",
+        "not the secret",
+        &tokens,
+    );
+    let Err(InitError::RequestError(err)) = result else {
+        panic!("expected the signature to be rejected");
+    };
+    assert_eq!(err.code(), Some(ErrorCode::InvalidMethodSignature));
+    assert!(!tokens.exists());
+}
+
+#[test]
+fn lastfm_cli_endpoint() {
+    let server = MockServer::start("key", "secret");
+    server.set_password("hunter2");
+    let dir = tempfile::tempdir().unwrap();
+    let workdir = dir.path();
+    let password_file = workdir.join("password");
+    std::fs::write(&password_file, "hunter2\n").unwrap();
+    let eclect = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_eclect"))
+            .args(args)
+            .args(["--workdir", workdir.to_str().unwrap()])
+            .args(["--endpoint", &server.endpoint()])
+            .args(["--lastfm-api-key", "key", "--lastfm-secret", "secret"])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    eclect(&[
+        "auth",
+        "--username",
+        "someone",
+        "--password-file",
+        password_file.to_str().unwrap(),
+    ]);
+    let whoami = eclect(&["whoami"]);
+    assert!(
+        whoami.contains("someone (https://www.last.fm/user/someone)"),
+        "{}",
+        whoami
+    );
+    let methods = server
+        .requests()
+        .iter()
+        .map(|request| request.method().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        methods,
+        ["auth.getMobileSession", "user.getInfo", "user.getInfo"]
+    );
+}
@@ -0,0 +1,364 @@
+use eclect::mpd::{MpdAddress, MpdError, MpdWatcher};
+use eclect::player::PlaybackStatus;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+trait FakeStream: Read + Write + Send + Sized + 'static {
+    fn try_clone(&self) -> std::io::Result<Self>;
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()>;
+    fn shutdown(&self);
+}
+
+impl FakeStream for std::net::TcpStream {
+    fn try_clone(&self) -> std::io::Result<Self> {
+        std::net::TcpStream::try_clone(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        std::net::TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn shutdown(&self) {
+        let _ = std::net::TcpStream::shutdown(self, std::net::Shutdown::Both);
+    }
+}
+
+#[cfg(unix)]
+impl FakeStream for std::os::unix::net::UnixStream {
+    fn try_clone(&self) -> std::io::Result<Self> {
+        std::os::unix::net::UnixStream::try_clone(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        std::os::unix::net::UnixStream::set_read_timeout(self, timeout)
+    }
+
+    fn shutdown(&self) {
+        let _ = std::os::unix::net::UnixStream::shutdown(self, std::net::Shutdown::Both);
+    }
+}
+
+struct FakeMpdState {
+    password: Option<&'static str>,
+    status: Vec<(&'static str, &'static str)>,
+    song: Vec<(&'static str, &'static str)>,
+}
+
+/// An MPD server that answers `password`, `status`, `currentsong`,
+/// `idle player` and `noidle` from a fixed state.
+#[derive(Clone)]
+struct FakeMpd {
+    state: Arc<Mutex<FakeMpdState>>,
+    /// Bumped for every player change.
+    changes: Arc<AtomicUsize>,
+    connections: Arc<AtomicUsize>,
+    /// Makes the current connections hang up.
+    kick: Arc<AtomicUsize>,
+}
+
+impl FakeMpd {
+    fn new(state: FakeMpdState) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(state)),
+            changes: Arc::new(AtomicUsize::new(0)),
+            connections: Arc::new(AtomicUsize::new(0)),
+            kick: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    fn listen_tcp(&self) -> MpdAddress {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = self.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let server = server.clone();
+                std::thread::spawn(move || server.serve(stream.unwrap()));
+            }
+        });
+        MpdAddress::Tcp(String::from("127.0.0.1"), port)
+    }
+
+    #[cfg(unix)]
+    fn listen_unix(&self, path: &std::path::Path) -> MpdAddress {
+        let listener = std::os::unix::net::UnixListener::bind(path).unwrap();
+        let server = self.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let server = server.clone();
+                std::thread::spawn(move || server.serve(stream.unwrap()));
+            }
+        });
+        MpdAddress::Unix(path.to_path_buf())
+    }
+
+    fn change(&self, state: &'static str) {
+        let mut fake_state = self.state.lock().unwrap();
+        fake_state
+            .status
+            .iter_mut()
+            .filter(|(key, _)| *key == "state")
+            .for_each(|(_, value)| *value = state);
+        self.changes.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn kick(&self) {
+        self.kick.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn serve<S: FakeStream>(&self, stream: S) {
+        self.connections.fetch_add(1, Ordering::SeqCst);
+        let kick = self.kick.load(Ordering::SeqCst);
+        let mut seen_changes = self.changes.load(Ordering::SeqCst);
+        let mut authorized = self.state.lock().unwrap().password.is_none();
+        let mut writer = stream.try_clone().unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(10)))
+            .unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        let mut idle = false;
+        writer.write_all(b"OK MPD 0.23.5\n").unwrap();
+        loop {
+            if self.kick.load(Ordering::SeqCst) != kick {
+                reader.get_ref().shutdown();
+                return;
+            }
+            let changes = self.changes.load(Ordering::SeqCst);
+            if idle && changes != seen_changes {
+                seen_changes = changes;
+                idle = false;
+                writer.write_all(b"changed: player\nOK\n").unwrap();
+            }
+            match reader.read_line(&mut line) {
+                Ok(0) => return,
+                Ok(_) => {}
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    continue;
+                }
+                Err(_) => return,
+            }
+            let command = std::mem::take(&mut line);
+            let command = command.trim_end();
+            let state = self.state.lock().unwrap();
+            let response = if command == "noidle" {
+                // Ignored unless idle, like MPD does.
+                let response = if idle { "OK\n" } else { "" };
+                idle = false;
+                String::from(response)
+            } else if let Some(password) = command.strip_prefix("password ") {
+                let expected = state
+                    .password
+                    .map(|password| format!("\"{}\"", password.replace('"', "\\\"")));
+                if Some(password.to_string()) == expected {
+                    authorized = true;
+                    String::from("OK\n")
+                } else {
+                    String::from("ACK [3@0] {password} incorrect password\n")
+                }
+            } else if !authorized {
+                format!("ACK [4@0] {{{}}} you don't have permission\n", command)
+            } else if command == "idle player" {
+                idle = true;
+                String::new()
+            } else {
+                let pairs = match command {
+                    "status" => &state.status,
+                    "currentsong" => &state.song,
+                    _ => {
+                        writer
+                            .write_all(
+                                format!("ACK [5@0] {{}} unknown command \"{}\"\n", command)
+                                    .as_bytes(),
+                            )
+                            .unwrap();
+                        continue;
+                    }
+                };
+                pairs
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}\n", key, value))
+                    .chain([String::from("OK\n")])
+                    .collect()
+            };
+            writer.write_all(response.as_bytes()).unwrap();
+        }
+    }
+}
+
+fn playing_state() -> FakeMpdState {
+    FakeMpdState {
+        password: None,
+        status: vec![
+            ("volume", "100"),
+            ("state", "play"),
+            ("song", "3"),
+            ("time", "12:180"),
+            ("elapsed", "12.500"),
+            ("duration", "180.000"),
+        ],
+        song: vec![
+            ("file", "music/First/Album/03 Song.flac"),
+            ("Artist", "First"),
+            ("Artist", "Second"),
+            ("Title", "Fake Song"),
+            ("Album", "Fake Album"),
+            ("Time", "180"),
+        ],
+    }
+}
+
+#[test]
+fn mpd_address() {
+    assert_eq!(
+        "music.local".parse(),
+        Ok(MpdAddress::Tcp(String::from("music.local"), 6600))
+    );
+    assert_eq!(
+        "music.local:6601".parse(),
+        Ok(MpdAddress::Tcp(String::from("music.local"), 6601))
+    );
+    assert_eq!(
+        "[::1]:6601".parse(),
+        Ok(MpdAddress::Tcp(String::from("::1"), 6601))
+    );
+    assert_eq!(
+        "[::1]".parse(),
+        Ok(MpdAddress::Tcp(String::from("::1"), 6600))
+    );
+    assert!("music.local:mpd".parse::<MpdAddress>().is_err());
+    assert!("".parse::<MpdAddress>().is_err());
+    #[cfg(unix)]
+    assert_eq!(
+        "/run/mpd/socket".parse(),
+        Ok(MpdAddress::Unix("/run/mpd/socket".into()))
+    );
+}
+
+#[test]
+fn mpd_current_song() {
+    let server = FakeMpd::new(playing_state());
+    let address = server.listen_tcp();
+    let mut watcher = MpdWatcher::new(address.clone(), None);
+
+    let state = watcher.current().unwrap().unwrap();
+    assert_eq!(state.player, format!("mpd {}", address));
+    assert_eq!(state.status, PlaybackStatus::Playing);
+    assert_eq!(state.track.name, "Fake Song");
+    assert_eq!(state.track.artist.unwrap().text, "First, Second");
+    assert_eq!(state.track.album.unwrap().text, "Fake Album");
+    assert!(state.track.image.is_empty());
+    assert_eq!(
+        state.track.url,
+        "https://www.last.fm/music/First,%20Second/_/Fake%20Song"
+    );
+    assert_eq!(state.length, Some(Duration::from_secs(180)));
+    assert_eq!(state.position, Some(Duration::from_millis(12_500)));
+
+    server.change("pause");
+    let state = watcher.current().unwrap().unwrap();
+    assert_eq!(state.status, PlaybackStatus::Paused);
+
+    server.change("stop");
+    assert!(watcher.current().unwrap().is_none());
+    assert_eq!(server.connections.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn mpd_untagged_song() {
+    let server = FakeMpd::new(FakeMpdState {
+        password: None,
+        status: vec![("state", "play"), ("time", "5:60")],
+        song: vec![("file", "music/Unsorted/some.track.mp3")],
+    });
+    let mut watcher = MpdWatcher::new(server.listen_tcp(), None);
+
+    let state = watcher.current().unwrap().unwrap();
+    assert_eq!(state.track.name, "some.track");
+    assert!(state.track.artist.is_none());
+    assert!(state.track.album.is_none());
+    assert_eq!(state.length, Some(Duration::from_secs(60)));
+    assert_eq!(state.position, Some(Duration::from_secs(5)));
+}
+
+#[test]
+fn mpd_password() {
+    let server = FakeMpd::new(FakeMpdState {
+        password: Some("pass\"word"),
+        ..playing_state()
+    });
+    let address = server.listen_tcp();
+
+    let mut watcher = MpdWatcher::new(address.clone(), None);
+    assert!(matches!(watcher.current(), Err(MpdError::Ack(_))));
+
+    let mut watcher = MpdWatcher::new(address.clone(), Some(String::from("wrong")));
+    assert!(matches!(watcher.current(), Err(MpdError::Ack(_))));
+
+    let mut watcher = MpdWatcher::new(address, Some(String::from("pass\"word")));
+    assert!(watcher.current().unwrap().is_some());
+}
+
+#[test]
+fn mpd_idle() {
+    let server = FakeMpd::new(playing_state());
+    let mut watcher = MpdWatcher::new(server.listen_tcp(), None);
+
+    assert!(!watcher.wait(Duration::from_millis(100)).unwrap());
+    // The connection is still usable after noidle.
+    assert!(watcher.current().unwrap().is_some());
+
+    let changer = server.clone();
+    let change = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        changer.change("pause");
+    });
+    assert!(watcher.wait(Duration::from_secs(5)).unwrap());
+    change.join().unwrap();
+    assert_eq!(
+        watcher.current().unwrap().unwrap().status,
+        PlaybackStatus::Paused
+    );
+
+    // Changes between idle calls are not lost.
+    server.change("play");
+    assert!(watcher.wait(Duration::from_secs(5)).unwrap());
+    assert_eq!(server.connections.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn mpd_reconnect() {
+    let server = FakeMpd::new(playing_state());
+    let mut watcher = MpdWatcher::new(server.listen_tcp(), None);
+
+    assert!(watcher.current().unwrap().is_some());
+    server.kick();
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(watcher.current().unwrap().is_some());
+    assert_eq!(server.connections.load(Ordering::SeqCst), 2);
+
+    server.kick();
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(!watcher.wait(Duration::from_millis(100)).unwrap());
+    assert_eq!(server.connections.load(Ordering::SeqCst), 3);
+}
+
+#[cfg(unix)]
+#[test]
+fn mpd_unix_socket() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("mpd.sock");
+    let server = FakeMpd::new(playing_state());
+    let mut watcher = MpdWatcher::new(server.listen_unix(&path), None);
+
+    let state = watcher.current().unwrap().unwrap();
+    assert_eq!(state.track.name, "Fake Song");
+    assert_eq!(state.player, format!("mpd {}", path.display()));
+}
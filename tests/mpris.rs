@@ -0,0 +1,278 @@
+#![cfg(feature = "mpris")]
+
+use dbus::arg::{PropMap, RefArg, Variant};
+use dbus::blocking::Connection;
+use dbus::channel::{Channel, MatchingReceiver, Sender};
+use dbus::message::{MatchRule, Message};
+use eclect::mpris::{MprisWatcher, OBJECT_PATH, PLAYER_INTERFACE, PlayerFilter};
+use eclect::player::PlaybackStatus;
+use std::io::BufRead;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::Duration;
+
+/// A dbus-daemon private to one test.
+struct Bus {
+    daemon: std::process::Child,
+    address: String,
+}
+
+impl Bus {
+    /// Starts a daemon, or returns `None` if dbus-daemon is not installed.
+    fn start() -> Option<Self> {
+        let mut daemon = match std::process::Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address=1"])
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+        {
+            Ok(daemon) => daemon,
+            Err(err) => {
+                eprintln!("skipping: could not start dbus-daemon: {}", err);
+                return None;
+            }
+        };
+        let mut address = String::new();
+        std::io::BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        Some(Self {
+            daemon,
+            address: address.trim().to_string(),
+        })
+    }
+
+    fn connect(&self) -> Connection {
+        let mut channel = Channel::open_private(&self.address).unwrap();
+        channel.register().unwrap();
+        Connection::from(channel)
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+struct FakeState {
+    status: &'static str,
+    title: &'static str,
+    artists: Vec<String>,
+}
+
+impl FakeState {
+    fn metadata(&self) -> PropMap {
+        let mut metadata = PropMap::new();
+        metadata.insert(
+            "xesam:title".to_string(),
+            Variant(Box::new(self.title.to_string())),
+        );
+        metadata.insert(
+            "xesam:artist".to_string(),
+            Variant(Box::new(self.artists.clone())),
+        );
+        metadata.insert(
+            "xesam:album".to_string(),
+            Variant(Box::new(String::from("Fake Album"))),
+        );
+        metadata.insert(
+            "mpris:artUrl".to_string(),
+            Variant(Box::new(String::from("https://example.com/cover.png"))),
+        );
+        metadata.insert(
+            "mpris:length".to_string(),
+            Variant(Box::new(180_000_000i64)),
+        );
+        metadata
+    }
+
+    fn property(&self, name: &str) -> Option<Variant<Box<dyn RefArg>>> {
+        match name {
+            "PlaybackStatus" => Some(Variant(Box::new(self.status.to_string()))),
+            "Metadata" => Some(Variant(Box::new(self.metadata()))),
+            "Position" => Some(Variant(Box::new(42_000_000i64))),
+            _ => None,
+        }
+    }
+}
+
+/// A player answering `org.freedesktop.DBus.Properties` calls from its own
+/// connection. Sending a status changes it and emits `PropertiesChanged`.
+struct FakePlayer {
+    status: mpsc::Sender<&'static str>,
+    stop: Arc<AtomicBool>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl FakePlayer {
+    fn start(bus: &Bus, name: &str, state: FakeState) -> Self {
+        let conn = bus.connect();
+        conn.request_name(
+            format!("org.mpris.MediaPlayer2.{}", name),
+            false,
+            true,
+            false,
+        )
+        .unwrap();
+        let state = Arc::new(Mutex::new(state));
+        let call_state = state.clone();
+        conn.start_receive(
+            MatchRule::new_method_call(),
+            Box::new(move |msg: Message, conn: &Connection| {
+                let state = call_state.lock().unwrap();
+                let reply = match (msg.interface().as_deref(), msg.member().as_deref()) {
+                    (Some("org.freedesktop.DBus.Properties"), Some("Get")) => {
+                        let (_, name): (String, String) = msg.read2().unwrap();
+                        match state.property(&name) {
+                            Some(value) => msg.method_return().append1(value),
+                            None => Message::error(
+                                &msg,
+                                &"org.freedesktop.DBus.Error.UnknownProperty".into(),
+                                &std::ffi::CString::new(name).unwrap(),
+                            ),
+                        }
+                    }
+                    _ => Message::error(
+                        &msg,
+                        &"org.freedesktop.DBus.Error.UnknownMethod".into(),
+                        c"unknown method",
+                    ),
+                };
+                let _ = conn.send(reply);
+                true
+            }),
+        );
+
+        let (status, statuses) = mpsc::channel::<&'static str>();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = std::thread::spawn(move || {
+            while !thread_stop.load(Ordering::SeqCst) {
+                conn.process(Duration::from_millis(20)).unwrap();
+                while let Ok(new_status) = statuses.try_recv() {
+                    state.lock().unwrap().status = new_status;
+                    let mut changed = PropMap::new();
+                    changed.insert(
+                        "PlaybackStatus".to_string(),
+                        Variant(Box::new(new_status.to_string())),
+                    );
+                    let signal = Message::new_signal(
+                        OBJECT_PATH,
+                        "org.freedesktop.DBus.Properties",
+                        "PropertiesChanged",
+                    )
+                    .unwrap()
+                    .append3(PLAYER_INTERFACE, changed, Vec::<String>::new());
+                    conn.send(signal).unwrap();
+                }
+            }
+        });
+        Self {
+            status,
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for FakePlayer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[test]
+fn mpris_filter() {
+    let filter = PlayerFilter {
+        allow: vec![],
+        deny: vec![String::from("firefox")],
+    };
+    assert!(filter.allows("org.mpris.MediaPlayer2.mpv"));
+    assert!(!filter.allows("org.mpris.MediaPlayer2.firefox"));
+    assert!(!filter.allows("org.mpris.MediaPlayer2.firefox.instance_1_42"));
+    assert!(filter.allows("org.mpris.MediaPlayer2.firefoxish"));
+    assert!(!filter.allows("org.freedesktop.DBus"));
+
+    let filter = PlayerFilter {
+        allow: vec![String::from("mpv"), String::from("spotify")],
+        deny: vec![String::from("spotify")],
+    };
+    assert!(filter.allows("org.mpris.MediaPlayer2.mpv"));
+    assert!(!filter.allows("org.mpris.MediaPlayer2.spotify"));
+    assert!(!filter.allows("org.mpris.MediaPlayer2.vlc"));
+}
+
+#[test]
+fn mpris_watch_player() {
+    let Some(bus) = Bus::start() else {
+        return;
+    };
+    let _browser = FakePlayer::start(
+        &bus,
+        "browser",
+        FakeState {
+            status: "Playing",
+            title: "Some Video",
+            artists: vec![],
+        },
+    );
+    let player = FakePlayer::start(
+        &bus,
+        "fake",
+        FakeState {
+            status: "Paused",
+            title: "Fake Song",
+            artists: vec![String::from("First"), String::from("Second")],
+        },
+    );
+
+    let unfiltered = MprisWatcher::new(bus.connect(), PlayerFilter::default()).unwrap();
+    let state = unfiltered.current().unwrap().unwrap();
+    assert_eq!(state.player, "org.mpris.MediaPlayer2.browser");
+
+    let watcher = MprisWatcher::new(
+        bus.connect(),
+        PlayerFilter {
+            allow: vec![],
+            deny: vec![String::from("browser")],
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        watcher.players().unwrap(),
+        vec!["org.mpris.MediaPlayer2.fake"]
+    );
+    let state = watcher.current().unwrap().unwrap();
+    assert_eq!(state.player, "org.mpris.MediaPlayer2.fake");
+    assert_eq!(state.status, PlaybackStatus::Paused);
+    assert_eq!(state.track.name, "Fake Song");
+    assert_eq!(state.track.artist.unwrap().text, "First, Second");
+    assert_eq!(state.track.album.unwrap().text, "Fake Album");
+    assert_eq!(state.track.image.len(), 1);
+    assert_eq!(state.track.image[0].size, "medium");
+    assert_eq!(state.track.image[0].text, "https://example.com/cover.png");
+    assert_eq!(
+        state.track.url,
+        "https://www.last.fm/music/First,%20Second/_/Fake%20Song"
+    );
+    assert_eq!(state.length, Some(Duration::from_secs(180)));
+    assert_eq!(state.position, Some(Duration::from_secs(42)));
+
+    // A new watcher always reports a change first.
+    assert!(watcher.wait(Duration::from_millis(100)).unwrap());
+    assert!(!watcher.wait(Duration::from_millis(100)).unwrap());
+
+    player.status.send("Playing").unwrap();
+    assert!(watcher.wait(Duration::from_secs(5)).unwrap());
+    let state = watcher.current().unwrap().unwrap();
+    assert_eq!(state.status, PlaybackStatus::Playing);
+
+    drop(player);
+    // The connection closing releases the name.
+    assert!(watcher.wait(Duration::from_secs(5)).unwrap());
+    assert!(watcher.current().unwrap().is_none());
+}
@@ -0,0 +1,313 @@
+use discord_rich_presence::DiscordIpc;
+use discord_rich_presence::activity::Activity;
+use eclect::backoff::Backoff;
+use eclect::discord::{
+    DiscordConnection, PlayTimes, Presence, PresenceTemplates, PresenceUpdate, track_values,
+};
+use eclect::player::build_track;
+use eclect::template::Template;
+use std::time::{Duration, Instant};
+
+/// Counts the calls made to it instead of talking to Discord.
+#[derive(Default)]
+struct CountingClient {
+    client_id: String,
+    set: usize,
+    cleared: usize,
+    connects: usize,
+    closes: usize,
+    failing: bool,
+    /// Discord is not running, so connecting fails.
+    offline: bool,
+}
+
+impl DiscordIpc for CountingClient {
+    fn get_client_id(&self) -> &String {
+        &self.client_id
+    }
+
+    fn connect_ipc(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.connects += 1;
+        if self.offline {
+            return Err("no discord ipc socket".into());
+        }
+        Ok(())
+    }
+
+    fn send_handshake(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+
+    fn write(&mut self, _data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+
+    fn read(&mut self, _buffer: &mut [u8]) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+
+    fn set_activity(&mut self, _activity: Activity) -> Result<(), Box<dyn std::error::Error>> {
+        if self.failing {
+            return Err("scripted failure".into());
+        }
+        self.set += 1;
+        Ok(())
+    }
+
+    fn clear_activity(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.failing {
+            return Err("scripted failure".into());
+        }
+        self.cleared += 1;
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.closes += 1;
+        Ok(())
+    }
+}
+
+const REASSERT: Duration = Duration::from_secs(300);
+
+fn show(
+    presence: &mut Presence,
+    client: &mut CountingClient,
+    track: Option<&prost_lastfm::Track>,
+    times: Option<PlayTimes>,
+    now: Instant,
+) -> PresenceUpdate {
+    let values = track
+        .map(|track| track_values(track, Some("someone"), None))
+        .unwrap_or_default();
+    presence.update(client, track, &values, times, now).unwrap()
+}
+
+#[test]
+fn presence_skips_unchanged() {
+    let mut client = CountingClient::default();
+    let mut presence = Presence::new(PresenceTemplates::default(), REASSERT);
+    let t0 = Instant::now();
+    let song = build_track("Song", "Artist", Some("Album"), None);
+
+    assert_eq!(
+        show(&mut presence, &mut client, Some(&song), None, t0),
+        PresenceUpdate::Set(String::from("Artist - Song"))
+    );
+    for secs in [15, 30, 45] {
+        let now = t0 + Duration::from_secs(secs);
+        assert_eq!(
+            show(&mut presence, &mut client, Some(&song), None, now),
+            PresenceUpdate::Unchanged
+        );
+    }
+    assert_eq!(client.set, 1);
+
+    // A different album is a different track.
+    let other_album = build_track("Song", "Artist", Some("Live"), None);
+    let now = t0 + Duration::from_secs(60);
+    show(&mut presence, &mut client, Some(&other_album), None, now);
+    assert_eq!(client.set, 2);
+
+    // So are new play times.
+    let times = PlayTimes {
+        start: 100,
+        end: Some(280),
+    };
+    show(
+        &mut presence,
+        &mut client,
+        Some(&other_album),
+        Some(times),
+        now,
+    );
+    show(
+        &mut presence,
+        &mut client,
+        Some(&other_album),
+        Some(times),
+        now,
+    );
+    assert_eq!(client.set, 3);
+
+    // Nothing playing clears once.
+    assert_eq!(
+        show(&mut presence, &mut client, None, None, now),
+        PresenceUpdate::Cleared
+    );
+    assert_eq!(
+        show(&mut presence, &mut client, None, None, now),
+        PresenceUpdate::Unchanged
+    );
+    assert_eq!((client.set, client.cleared), (3, 1));
+}
+
+#[test]
+fn presence_rendered_text_changes() {
+    let mut client = CountingClient::default();
+    let templates = PresenceTemplates {
+        state: Template::parse("[{playcount} plays]").unwrap(),
+        ..PresenceTemplates::default()
+    };
+    let mut presence = Presence::new(templates, REASSERT);
+    let now = Instant::now();
+    let song = build_track("Song", "Artist", None, None);
+    let values = |playcount| track_values(&song, None, Some(playcount));
+
+    presence
+        .update(&mut client, Some(&song), &values(1), None, now)
+        .unwrap();
+    presence
+        .update(&mut client, Some(&song), &values(1), None, now)
+        .unwrap();
+    presence
+        .update(&mut client, Some(&song), &values(2), None, now)
+        .unwrap();
+    assert_eq!(client.set, 2);
+}
+
+#[test]
+fn presence_reasserts() {
+    let mut client = CountingClient::default();
+    let mut presence = Presence::new(PresenceTemplates::default(), REASSERT);
+    let t0 = Instant::now();
+    let song = build_track("Song", "Artist", None, None);
+
+    show(&mut presence, &mut client, Some(&song), None, t0);
+    show(
+        &mut presence,
+        &mut client,
+        Some(&song),
+        None,
+        t0 + REASSERT / 2,
+    );
+    assert_eq!(client.set, 1);
+    assert_eq!(
+        show(&mut presence, &mut client, Some(&song), None, t0 + REASSERT),
+        PresenceUpdate::Set(String::from("Artist - Song"))
+    );
+    assert_eq!(client.set, 2);
+
+    // A reconnect loses the presence, so it is sent again.
+    let now = t0 + REASSERT + Duration::from_secs(1);
+    presence.invalidate();
+    show(&mut presence, &mut client, Some(&song), None, now);
+    assert_eq!(client.set, 3);
+
+    // Clearing is reasserted too.
+    show(&mut presence, &mut client, None, None, now);
+    show(&mut presence, &mut client, None, None, now + REASSERT);
+    assert_eq!(client.cleared, 2);
+}
+
+#[test]
+fn presence_retries_after_error() {
+    let mut client = CountingClient::default();
+    let mut presence = Presence::new(PresenceTemplates::default(), REASSERT);
+    let now = Instant::now();
+    let song = build_track("Song", "Artist", None, None);
+    let values = track_values(&song, None, None);
+
+    show(&mut presence, &mut client, Some(&song), None, now);
+    client.failing = true;
+    assert!(
+        presence
+            .update(&mut client, None, &values, None, now)
+            .is_err()
+    );
+    client.failing = false;
+    // The failed clear may or may not have reached Discord, so the same track
+    // is sent again.
+    show(&mut presence, &mut client, Some(&song), None, now);
+    assert_eq!((client.set, client.cleared), (2, 0));
+}
+
+#[test]
+fn connection_waits_for_discord() {
+    let client = CountingClient {
+        offline: true,
+        ..CountingClient::default()
+    };
+    let retry = Duration::from_secs(5);
+    let mut discord = DiscordConnection::new(client, Backoff::new(retry, retry * 4));
+    let t0 = Instant::now();
+    assert!(discord.client().is_none());
+    assert_eq!(discord.until_retry(t0), Some(Duration::ZERO));
+
+    // Each failure waits longer, within the jitter.
+    let mut now = t0;
+    for nominal in [retry, retry * 2, retry * 4, retry * 4] {
+        assert!(discord.maintain(now).is_err());
+        let wait = discord.until_retry(now).unwrap();
+        assert!(wait >= nominal && wait <= retry * 4, "{:?}", wait);
+        // Not due yet, so nothing is tried.
+        assert!(!discord.maintain(now + wait / 2).unwrap());
+        now += wait;
+    }
+    assert_eq!(discord.get_mut().connects, 4);
+}
+
+#[test]
+fn connection_pushes_presence_once_connected() {
+    let client = CountingClient {
+        offline: true,
+        ..CountingClient::default()
+    };
+    let retry = Duration::from_secs(5);
+    let mut discord = DiscordConnection::new(client, Backoff::new(retry, retry * 4));
+    let mut presence = Presence::new(PresenceTemplates::default(), REASSERT);
+    let song = build_track("Song", "Artist", None, None);
+    let t0 = Instant::now();
+    assert!(discord.maintain(t0).is_err());
+    assert!(discord.client().is_none());
+
+    // Discord starts. Connecting resets the backoff.
+    let now = t0 + retry * 2;
+    discord.get_mut().offline = false;
+    assert!(discord.maintain(now).unwrap());
+    assert!(discord.is_connected());
+    assert_eq!(discord.until_retry(now), None);
+    presence.invalidate();
+    let client = discord.client().unwrap();
+    show(&mut presence, client, Some(&song), None, now);
+    assert_eq!(client.set, 1);
+
+    // Losing the connection retries right away, and shows the track again.
+    client.failing = true;
+    assert!(
+        presence
+            .update(client, None, &Default::default(), None, now)
+            .is_err()
+    );
+    discord.disconnect();
+    presence.invalidate();
+    assert_eq!(discord.until_retry(now), Some(Duration::ZERO));
+    assert!(discord.maintain(now).unwrap());
+    let client = discord.client().unwrap();
+    assert_eq!(client.closes, 1);
+    client.failing = false;
+    show(&mut presence, client, Some(&song), None, now);
+    assert_eq!(client.set, 2);
+}
+
+#[test]
+fn connection_shutdown_clears_presence() {
+    let retry = Duration::from_secs(5);
+    let mut discord =
+        DiscordConnection::new(CountingClient::default(), Backoff::new(retry, retry * 4));
+    // Nothing to clear before connecting.
+    discord.shutdown().unwrap();
+    assert_eq!(discord.get_mut().cleared, 0);
+
+    assert!(discord.maintain(Instant::now()).unwrap());
+    discord.shutdown().unwrap();
+    assert!(!discord.is_connected());
+    let client = discord.get_mut();
+    assert_eq!((client.cleared, client.closes), (1, 1));
+
+    // A failed clear still closes the connection.
+    client.failing = true;
+    assert!(discord.maintain(Instant::now()).unwrap());
+    assert!(discord.shutdown().is_err());
+    assert_eq!(discord.get_mut().closes, 2);
+}
@@ -0,0 +1,77 @@
+use eclect::secret::{Secret, SecretError, SecretSource};
+
+fn parse(s: &str) -> SecretSource {
+    s.parse().unwrap()
+}
+
+#[test]
+fn secret_parse() {
+    assert_eq!(
+        parse("env:LASTFM_KEY"),
+        SecretSource::Env(String::from("LASTFM_KEY"))
+    );
+    assert_eq!(
+        parse("file:~/key"),
+        SecretSource::File(String::from("~/key"))
+    );
+    assert_eq!(
+        parse("cmd:pass show lastfm"),
+        SecretSource::Command(String::from("pass show lastfm"))
+    );
+    assert_eq!(
+        parse("0123abcd"),
+        SecretSource::Inline(Secret::new("0123abcd"))
+    );
+    assert!(matches!(
+        "env:".parse::<SecretSource>(),
+        Err(SecretError::Empty(_))
+    ));
+}
+
+#[test]
+fn secret_debug_redacted() {
+    let source = parse("hunter2");
+    assert!(!format!("{:?}", source).contains("hunter2"));
+    assert!(!format!("{:?}", source.resolve().unwrap()).contains("hunter2"));
+    assert_eq!(source.resolve().unwrap().expose(), "hunter2");
+}
+
+#[test]
+fn secret_env() {
+    let name = format!("ECLECT_SECRET_TEST_{}", std::process::id());
+    // SAFETY: no other test reads or writes this variable.
+    unsafe { std::env::set_var(&name, "from env\n") };
+    assert_eq!(
+        SecretSource::Env(name.clone()).resolve().unwrap().expose(),
+        "from env"
+    );
+    unsafe { std::env::remove_var(&name) };
+    assert!(matches!(
+        SecretSource::Env(name).resolve(),
+        Err(SecretError::Env(_))
+    ));
+}
+
+#[test]
+fn secret_file_trimmed() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("secret");
+    std::fs::write(&path, "from file\n").unwrap();
+    let source = SecretSource::File(path.to_string_lossy().into_owned());
+    assert_eq!(source.resolve().unwrap().expose(), "from file");
+    std::fs::write(&path, "\n").unwrap();
+    assert!(matches!(source.resolve(), Err(SecretError::Empty(_))));
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(source.resolve(), Err(SecretError::File(_))));
+}
+
+#[cfg(unix)]
+#[test]
+fn secret_command() {
+    let source = parse("cmd:printf 'from command\\n'");
+    assert_eq!(source.resolve().unwrap().expose(), "from command");
+    // Output is left out of the error, in case it is the secret.
+    let err = parse("cmd:echo leaked; exit 3").resolve().unwrap_err();
+    assert!(matches!(err, SecretError::Command(_)));
+    assert!(!err.to_string().contains("leaked"));
+}
@@ -0,0 +1,171 @@
+use eclect::mpd::MpdError;
+use eclect::player::{PlaybackStatus, PlayerState, build_track};
+use eclect::source::{SourceError, SourceEvent, Sources, TrackSource};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Clone)]
+enum Script {
+    Stopped,
+    Status(PlaybackStatus, u64),
+    Failing,
+}
+
+/// A source that reports whatever the test last set.
+struct ScriptedSource {
+    name: &'static str,
+    script: Arc<Mutex<Script>>,
+}
+
+impl ScriptedSource {
+    fn new(name: &'static str) -> (Self, Arc<Mutex<Script>>) {
+        let script = Arc::new(Mutex::new(Script::Stopped));
+        (
+            Self {
+                name,
+                script: script.clone(),
+            },
+            script,
+        )
+    }
+}
+
+impl TrackSource for ScriptedSource {
+    fn name(&self) -> String {
+        self.name.to_string()
+    }
+
+    fn current(&mut self) -> Result<Option<PlayerState>, SourceError> {
+        match self.script.lock().unwrap().clone() {
+            Script::Stopped => Ok(None),
+            Script::Status(status, position) => Ok(Some(PlayerState {
+                player: self.name.to_string(),
+                status,
+                track: build_track("Song", self.name, None, None),
+                length: Some(Duration::from_secs(180)),
+                position: Some(Duration::from_secs(position)),
+            })),
+            Script::Failing => Err(MpdError::Protocol(String::from("scripted failure")).into()),
+        }
+    }
+
+    fn wait(&mut self, timeout: Duration) -> Result<(), SourceError> {
+        std::thread::sleep(timeout);
+        Ok(())
+    }
+}
+
+fn set(script: &Arc<Mutex<Script>>, value: Script) {
+    *script.lock().unwrap() = value;
+}
+
+/// The player of the next change, skipping errors.
+fn next_change(sources: &mut Sources) -> Option<(String, PlaybackStatus)> {
+    loop {
+        match sources.recv_timeout(Duration::from_secs(5)) {
+            None => panic!("no change reported"),
+            Some(SourceEvent::Error(..)) => continue,
+            Some(SourceEvent::Changed(state)) => {
+                return state.map(|state| (state.player, state.status));
+            }
+            Some(SourceEvent::Interrupted) => panic!("unexpected interrupt"),
+        }
+    }
+}
+
+fn spawn(sources: Vec<ScriptedSource>) -> Sources {
+    Sources::spawn(
+        sources
+            .into_iter()
+            .map(|source| Box::new(source) as Box<dyn TrackSource>)
+            .collect(),
+        Duration::from_millis(10),
+    )
+}
+
+#[test]
+fn source_fallback() {
+    let (first, first_script) = ScriptedSource::new("first");
+    let (second, second_script) = ScriptedSource::new("second");
+    set(&second_script, Script::Status(PlaybackStatus::Playing, 0));
+    let mut sources = spawn(vec![first, second]);
+
+    let playing = |name: &str| Some((name.to_string(), PlaybackStatus::Playing));
+    let paused = |name: &str| Some((name.to_string(), PlaybackStatus::Paused));
+
+    assert_eq!(next_change(&mut sources), playing("second"));
+    set(&first_script, Script::Status(PlaybackStatus::Playing, 0));
+    assert_eq!(next_change(&mut sources), playing("first"));
+    // Playing wins over paused, whatever the order.
+    set(&first_script, Script::Status(PlaybackStatus::Paused, 0));
+    assert_eq!(next_change(&mut sources), playing("second"));
+    set(&second_script, Script::Stopped);
+    assert_eq!(next_change(&mut sources), paused("first"));
+    set(&first_script, Script::Stopped);
+    assert_eq!(next_change(&mut sources), None);
+    assert!(sources.current().is_none());
+}
+
+#[test]
+fn source_error_falls_back() {
+    let (first, first_script) = ScriptedSource::new("first");
+    let (second, second_script) = ScriptedSource::new("second");
+    set(&first_script, Script::Status(PlaybackStatus::Playing, 0));
+    set(&second_script, Script::Status(PlaybackStatus::Playing, 0));
+    let mut sources = spawn(vec![first, second]);
+    assert_eq!(next_change(&mut sources).unwrap().0, "first");
+
+    set(&first_script, Script::Failing);
+    let Some(SourceEvent::Error(error, _)) = sources.recv_timeout(Duration::from_secs(5)) else {
+        panic!("no error reported");
+    };
+    assert_eq!(error, "first");
+    assert_eq!(next_change(&mut sources).unwrap().0, "second");
+
+    set(&first_script, Script::Status(PlaybackStatus::Playing, 0));
+    assert_eq!(next_change(&mut sources).unwrap().0, "first");
+}
+
+#[test]
+fn source_reports_only_changes() {
+    let (source, script) = ScriptedSource::new("only");
+    set(&script, Script::Status(PlaybackStatus::Playing, 10));
+    let mut sources = spawn(vec![source]);
+    assert!(next_change(&mut sources).is_some());
+
+    // Playing on is not a change.
+    set(&script, Script::Status(PlaybackStatus::Playing, 20));
+    assert!(sources.recv_timeout(Duration::from_millis(200)).is_none());
+    assert_eq!(
+        sources.current().unwrap().position,
+        Some(Duration::from_secs(10))
+    );
+
+    // Starting the track over is.
+    set(&script, Script::Status(PlaybackStatus::Playing, 0));
+    assert!(next_change(&mut sources).is_some());
+    assert_eq!(
+        sources.current().unwrap().position,
+        Some(Duration::from_secs(0))
+    );
+}
+
+#[test]
+fn source_interrupt() {
+    let (source, script) = ScriptedSource::new("only");
+    set(&script, Script::Status(PlaybackStatus::Playing, 0));
+    let mut sources = spawn(vec![source]);
+    assert!(next_change(&mut sources).is_some());
+
+    // Interrupting wakes the wait long before the timeout, from any thread.
+    let interrupter = sources.interrupter();
+    std::thread::spawn(move || interrupter.interrupt());
+    let started = std::time::Instant::now();
+    assert!(matches!(
+        sources.recv_timeout(Duration::from_secs(60)),
+        Some(SourceEvent::Interrupted)
+    ));
+    assert!(started.elapsed() < Duration::from_secs(30));
+    // The current state is kept.
+    assert!(sources.current().is_some());
+}
@@ -0,0 +1,137 @@
+use eclect::discord::{PresenceTemplates, PresenceText, track_values};
+use eclect::player::build_track;
+use eclect::template::{Template, TemplateValues};
+
+fn values(fields: &[(&'static str, &str)]) -> TemplateValues {
+    fields
+        .iter()
+        .map(|(field, value)| (*field, value.to_string()))
+        .collect()
+}
+
+fn render(template: &str, fields: &[(&'static str, &str)]) -> String {
+    Template::parse(template).unwrap().render(&values(fields))
+}
+
+#[test]
+fn template_fields() {
+    let fields = [("title", "Song"), ("artist", "Artist"), ("playcount", "12")];
+    assert_eq!(render("{title}", &fields), "Song");
+    assert_eq!(render("{artist} - {title}", &fields), "Artist - Song");
+    assert_eq!(
+        render("played {playcount} times", &fields),
+        "played 12 times"
+    );
+    // Missing fields outside a section render as nothing.
+    assert_eq!(render("{title} on {album}", &fields), "Song on ");
+    assert_eq!(render("{{{title}}} [[live]]", &fields), "{Song} [live]");
+    assert_eq!(render("", &fields), "");
+}
+
+#[test]
+fn template_sections() {
+    let full = [
+        ("artist", "Artist"),
+        ("album", "Album"),
+        ("user", "someone"),
+    ];
+    let sparse = [("artist", "Artist")];
+    assert_eq!(render("{artist}[ - {album}]", &full), "Artist - Album");
+    assert_eq!(render("{artist}[ - {album}]", &sparse), "Artist");
+    // Sections without fields always show.
+    assert_eq!(render("[by ]{artist}", &sparse), "by Artist");
+    // An inner section does not hide the outer one.
+    assert_eq!(
+        render("[{artist}[ ({album})] for {user}]", &full),
+        "Artist (Album) for someone"
+    );
+    assert_eq!(
+        render(
+            "[{artist}[ ({album})] for {user}]",
+            &[("artist", "Artist"), ("user", "someone")]
+        ),
+        "Artist for someone"
+    );
+    assert_eq!(render("[{artist}[ ({album})] for {user}]", &sparse), "");
+    // Empty values count as missing.
+    assert_eq!(render("x[{album}]", &[("album", "")]), "x");
+}
+
+#[test]
+fn template_errors() {
+    let error = |template: &str| Template::parse(template).unwrap_err();
+    let err = error("{title} by {band}");
+    assert_eq!(err.position, 11);
+    assert!(err.message.contains("unknown field {band}"));
+    assert_eq!(error("{title").position, 0);
+    assert_eq!(error("{artist}[ - {album}").position, 8);
+    assert_eq!(error("{artist}]").position, 8);
+    assert_eq!(error("title}").position, 5);
+    assert!(Template::parse("[[{title}]]").is_ok());
+}
+
+#[test]
+fn template_uses() {
+    let template = Template::parse("{title}[ ({playcount} plays)]").unwrap();
+    assert!(template.uses("playcount"));
+    assert!(!template.uses("user"));
+    assert_eq!(template.as_str(), "{title}[ ({playcount} plays)]");
+}
+
+#[test]
+fn presence_render() {
+    let track = build_track(
+        "Song",
+        "Artist",
+        None,
+        Some("https://example.com/cover.jpg"),
+    );
+    let defaults = PresenceTemplates::default();
+    assert!(!defaults.uses("playcount"));
+    assert_eq!(
+        defaults.render(&track_values(&track, Some("someone"), None)),
+        PresenceText {
+            details: Some(String::from("Song")),
+            state: None,
+            large_text: Some(String::from("Artist")),
+            small_text: None,
+            buttons: vec![(
+                String::from("View on last.fm"),
+                String::from("https://www.last.fm/music/Artist/_/Song")
+            )],
+        }
+    );
+
+    let custom = PresenceTemplates {
+        state: Template::parse("[{playcount} plays by ]{user}").unwrap(),
+        buttons: vec![
+            (
+                Template::parse("Profile").unwrap(),
+                Template::parse("https://www.last.fm/user/{user}").unwrap(),
+            ),
+            (
+                Template::parse("Album").unwrap(),
+                Template::parse("[https://example.com/{album}]").unwrap(),
+            ),
+        ],
+        ..PresenceTemplates::default()
+    };
+    assert!(custom.uses("playcount"));
+    let text = custom.render(&track_values(&track, Some("someone"), Some(3)));
+    assert_eq!(text.state.as_deref(), Some("3 plays by someone"));
+    // The album button has no URL without an album.
+    assert_eq!(
+        text.buttons,
+        vec![(
+            String::from("Profile"),
+            String::from("https://www.last.fm/user/someone")
+        )]
+    );
+}
+
+#[test]
+fn presence_truncates() {
+    let track = build_track(&"x".repeat(200), "Artist", None, None);
+    let text = PresenceTemplates::default().render(&track_values(&track, None, None));
+    assert_eq!(text.details.unwrap().chars().count(), 128);
+}
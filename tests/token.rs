@@ -0,0 +1,132 @@
+use base64::Engine;
+use eclect::lastfm::InitToken;
+use eclect::token::{PassphraseSource, TokenError, TokenStore};
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+/// A token path in a temporary directory, removed when the guard is dropped.
+fn temp_path() -> (TempDir, PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("token.bin");
+    (dir, path)
+}
+
+fn passphrase(dir: &TempDir, passphrase: &str) -> PassphraseSource {
+    let path = dir.path().join("passphrase");
+    std::fs::write(&path, format!("{}\n", passphrase)).unwrap();
+    PassphraseSource::File(path)
+}
+
+fn is_session(token: Result<InitToken, TokenError>, expected: &str) -> bool {
+    matches!(token, Ok(InitToken::Session(key)) if key == expected)
+}
+
+#[cfg(unix)]
+fn mode(path: &std::path::Path) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path).unwrap().permissions().mode() & 0o777
+}
+
+#[test]
+fn token_round_trip() {
+    let (_dir, path) = temp_path();
+    let store = TokenStore::new(&path, None);
+    store
+        .write(&InitToken::Session(String::from("key")))
+        .unwrap();
+    assert!(
+        std::fs::read_to_string(&path)
+            .unwrap()
+            .starts_with("eclect-token:1:plain:")
+    );
+    #[cfg(unix)]
+    assert_eq!(mode(&path), 0o600);
+    assert!(is_session(store.read(), "key"));
+}
+
+#[test]
+fn token_migrates_legacy_file() {
+    let (_dir, path) = temp_path();
+    let legacy = base64::engine::general_purpose::STANDARD.encode(r#"{"Session":"old-key"}"#);
+    std::fs::write(&path, legacy).unwrap();
+    // Written with the default permissions at the time.
+    #[cfg(unix)]
+    std::fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o644)).unwrap();
+    let store = TokenStore::new(&path, None);
+    assert!(is_session(store.read(), "old-key"));
+    assert!(
+        std::fs::read_to_string(&path)
+            .unwrap()
+            .starts_with("eclect-token:1:plain:")
+    );
+    #[cfg(unix)]
+    assert_eq!(mode(&path), 0o600);
+}
+
+#[cfg(unix)]
+#[test]
+fn token_refuses_readable_file() {
+    use std::os::unix::fs::PermissionsExt;
+    let (_dir, path) = temp_path();
+    let store = TokenStore::new(&path, None);
+    store
+        .write(&InitToken::Session(String::from("key")))
+        .unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+    assert!(matches!(store.read(), Err(TokenError::Permissions(_))));
+    // Checked before the token is read at all.
+    std::fs::write(&path, "eclect-token:1:plain:not base64").unwrap();
+    assert!(matches!(store.read(), Err(TokenError::Permissions(_))));
+}
+
+#[test]
+fn token_encrypted_round_trip() {
+    let (dir, path) = temp_path();
+    let store = TokenStore::new(&path, Some(passphrase(&dir, "correct horse")));
+    store
+        .write(&InitToken::Session(String::from("secret-key")))
+        .unwrap();
+    let contents = std::fs::read_to_string(&path).unwrap();
+    assert!(contents.starts_with("eclect-token:1:encrypted:"));
+    assert!(!contents.contains("secret-key"));
+    assert!(is_session(store.read(), "secret-key"));
+    // A fresh store reads the passphrase again.
+    let store = TokenStore::new(&path, Some(passphrase(&dir, "correct horse")));
+    assert!(is_session(store.read(), "secret-key"));
+}
+
+#[test]
+fn token_wrong_passphrase() {
+    let (dir, path) = temp_path();
+    TokenStore::new(&path, Some(passphrase(&dir, "right")))
+        .write(&InitToken::Session(String::from("key")))
+        .unwrap();
+    let store = TokenStore::new(&path, Some(passphrase(&dir, "wrong")));
+    assert!(matches!(store.read(), Err(TokenError::Passphrase(_))));
+    let store = TokenStore::new(&path, None);
+    assert!(matches!(store.read(), Err(TokenError::Passphrase(_))));
+}
+
+#[test]
+fn token_encrypts_plain_file() {
+    let (dir, path) = temp_path();
+    TokenStore::new(&path, None)
+        .write(&InitToken::Auth(String::from("auth-token")))
+        .unwrap();
+    let store = TokenStore::new(&path, Some(passphrase(&dir, "passphrase")));
+    assert!(matches!(store.read(), Ok(InitToken::Auth(token)) if token == "auth-token"));
+    assert!(
+        std::fs::read_to_string(&path)
+            .unwrap()
+            .starts_with("eclect-token:1:encrypted:")
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn token_passphrase_command() {
+    let source = PassphraseSource::Command(String::from("printf 'from command\\n'"));
+    assert_eq!(source.read().unwrap(), "from command");
+    let failing = PassphraseSource::Command(String::from("exit 1"));
+    assert!(matches!(failing.read(), Err(TokenError::Passphrase(_))));
+}